@@ -4,5 +4,8 @@
 pub mod scd4x;
 mod sensirion;
 pub mod sgp40;
+#[cfg(test)]
+mod test_support;
+pub mod voc_index;
 
-pub use sensirion::Error;
+pub use sensirion::{Cmd, Error};
@@ -0,0 +1,781 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use super::*;
+use crate::sensirion::asynch::Sensor;
+
+/// Async SCD4x driver. Mirrors the runtime-checked blocking [`super::SCD4x`].
+#[derive(Debug)]
+pub struct SCD4x<I2C, D> {
+    sensor: Sensor<I2C, D>,
+    state: State,
+    stopped_from_periodic: bool,
+    ambient_pressure: Option<u32>,
+    variant: Option<Variant>,
+}
+
+impl<I2C: I2c, D: DelayNs> SCD4x<I2C, D> {
+    /// Creates the driver. The sensor is assumed to be idle, as it is after power-up.
+    pub fn new(i2c: I2C, delay: D) -> Self {
+        Self {
+            sensor: Sensor::new(i2c, ADDR, delay),
+            state: State::Idle,
+            stopped_from_periodic: false,
+            ambient_pressure: None,
+            variant: None,
+        }
+    }
+
+    /// Returns the sensor variant cached by get_sensor_variant, if known.
+    pub fn variant(&self) -> Option<Variant> {
+        self.variant
+    }
+
+    /// Sets the sensor variant without querying the sensor, for when the part
+    /// is known at build time.
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    /// Polls the sensor for whether data from a periodic or single shot measurement is ready to be read out.
+    pub async fn get_data_ready_status(&mut self) -> Result<bool, Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let status = self
+            .sensor
+            .one_word_command(&commands::GET_DATA_READY_STATUS)
+            .await?;
+
+        Ok(decode_data_ready_status(status))
+    }
+
+    /// Reading out the serial number can be used to identify the chip and to verify the presence of the sensor.
+    pub async fn get_serial_number(&mut self) -> Result<u64, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let words = self.sensor.read_words(&commands::GET_SERIAL_NUMBER).await?;
+
+        Ok(serial_number(&words))
+    }
+
+    /// The perform_self_test command can be used as an end-of-line test to check the sensor functionality.
+    pub async fn start_self_test(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .start_command(&commands::PERFORM_SELF_TEST)
+            .await
+    }
+
+    /// Returns true if no malfunction detected, false if failed.
+    /// Result is available 10s after self-test is started.
+    pub async fn read_self_test_result(&mut self) -> Result<bool, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let status = self.sensor.read_response_word().await?;
+
+        Ok(status == 0)
+    }
+
+    /// Runs the self-test and waits 10 s for its result.
+    pub async fn perform_self_test(&mut self) -> Result<SelfTestReport, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .send_command(&commands::PERFORM_SELF_TEST)
+            .await?;
+        let malfunction = self.sensor.read_response_word().await?;
+
+        Ok(SelfTestReport { malfunction })
+    }
+
+    /// Reads out the SCD4x sensor variant and caches it for capability checks.
+    pub async fn get_sensor_variant(&mut self) -> Result<Variant, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let status = self
+            .sensor
+            .one_word_command(&commands::GET_SENSOR_VARIANT)
+            .await?;
+
+        let variant = decode_sensor_variant(status)?;
+        self.variant = Some(variant);
+        Ok(variant)
+    }
+
+    /// Returns the cached sensor variant, reading it out on first use.
+    async fn sensor_variant(&mut self) -> Result<Variant, Error<I2C::Error>> {
+        match self.variant {
+            Some(variant) => Ok(variant),
+            None => self.get_sensor_variant().await,
+        }
+    }
+
+    /// Returns the sensor to the idle mode. Waits 500 ms for the sensor to
+    /// become responsive before returning.
+    pub async fn stop_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .send_command(&commands::STOP_PERIODIC_MEASUREMENTS)
+            .await?;
+        self.state = State::Idle;
+        self.stopped_from_periodic = true;
+        Ok(())
+    }
+
+    /// Starts the periodic measurement mode. The signal update interval is 5 seconds.
+    pub async fn start_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .send_command(&commands::START_PERIODIC_MEASUREMENTS)
+            .await?;
+        self.state = State::Periodic;
+        Ok(())
+    }
+
+    /// Starts the low power periodic measurement mode. The signal update
+    /// interval is approximately 30 seconds.
+    pub async fn start_low_power_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .send_command(&commands::START_LOW_POWER_PERIODIC_MEASUREMENT)
+            .await?;
+        self.state = State::LowPowerPeriodic;
+        Ok(())
+    }
+
+    /// Reads the sensor output. The measurement data can only be read out once
+    /// per signal update interval as the buffer is emptied upon read-out.
+    pub async fn read_measurement(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let response = self.sensor.read_words(&commands::READ_MEASUREMENT).await?;
+        Ok(Measurement::from_words(&response))
+    }
+
+    /// Reads the sensor output like read_measurement, but returns the raw words
+    /// without converting them.
+    pub async fn read_measurement_raw(&mut self) -> Result<RawMeasurement, Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let response = self.sensor.read_words(&commands::READ_MEASUREMENT).await?;
+        Ok(RawMeasurement::from_words(&response))
+    }
+
+    /// Reads the sensor output like read_measurement, but converts it with
+    /// integer math only.
+    pub async fn read_measurement_fixed(&mut self) -> Result<FixedMeasurement, Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let response = self.sensor.read_words(&commands::READ_MEASUREMENT).await?;
+        Ok(FixedMeasurement::from_words(&response))
+    }
+
+    /// Polls the data ready status every 100 ms until a measurement is available
+    /// and reads it out. Fails with `Error::Timeout` if no measurement becomes
+    /// available within approximately `timeout_ms`.
+    pub async fn wait_for_measurement(
+        &mut self,
+        timeout_ms: u32,
+    ) -> Result<Measurement, Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let mut waited_ms = 0;
+        while !self.get_data_ready_status().await? {
+            if waited_ms >= timeout_ms {
+                return Err(Error::Timeout);
+            }
+            let interval_ms = DATA_READY_POLL_INTERVAL_MS.min(timeout_ms - waited_ms);
+            self.sensor.delay_ms(interval_ms).await;
+            waited_ms += interval_ms;
+        }
+
+        self.read_measurement().await
+    }
+
+    /// Sets the temperature offset in °C.
+    pub async fn set_temperature_offset(&mut self, celsius: f32) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let ticks = encode_temperature_offset(celsius)?;
+        self.sensor
+            .write_command_with_args(&commands::SET_TEMPERATURE_OFFSET, &[ticks])
+            .await
+    }
+
+    /// Reads out the current temperature offset in °C.
+    pub async fn get_temperature_offset(&mut self) -> Result<f32, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let ticks = self
+            .sensor
+            .one_word_command(&commands::GET_TEMPERATURE_OFFSET)
+            .await?;
+
+        Ok(decode_temperature_offset(ticks))
+    }
+
+    /// Sets the sensor altitude in meters above sea level.
+    pub async fn set_sensor_altitude(&mut self, meters: u16) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let meters = check_sensor_altitude(meters)?;
+        self.sensor
+            .write_command_with_args(&commands::SET_SENSOR_ALTITUDE, &[meters])
+            .await
+    }
+
+    /// Reads out the currently configured sensor altitude in meters above sea level.
+    pub async fn get_sensor_altitude(&mut self) -> Result<u16, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .one_word_command(&commands::GET_SENSOR_ALTITUDE)
+            .await
+    }
+
+    /// Sets the ambient pressure in Pa. Can be issued during periodic measurement.
+    pub async fn set_ambient_pressure(&mut self, pascals: u32) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let ticks = encode_ambient_pressure(pascals)?;
+        self.sensor
+            .write_command_with_args(&commands::SET_AMBIENT_PRESSURE, &[ticks])
+            .await?;
+        self.ambient_pressure = Some(pascals);
+        Ok(())
+    }
+
+    /// Reads out the ambient pressure in Pa used for compensation.
+    pub async fn get_ambient_pressure(&mut self) -> Result<u32, Error<I2C::Error>> {
+        self.state.ensure_awake()?;
+        let ticks = self
+            .sensor
+            .one_word_command(&commands::GET_AMBIENT_PRESSURE)
+            .await?;
+
+        Ok(decode_ambient_pressure(ticks))
+    }
+
+    /// Sends the ambient pressure from `source` if it differs from the last
+    /// value sent by at least `threshold_pa`. Returns true if the sensor was updated.
+    pub async fn update_ambient_pressure<F>(
+        &mut self,
+        source: F,
+        threshold_pa: u32,
+    ) -> Result<bool, Error<I2C::Error>>
+    where
+        F: FnOnce() -> Option<u32>,
+    {
+        self.state.ensure_awake()?;
+        let Some(pascals) = source() else {
+            return Ok(false);
+        };
+
+        if !ambient_pressure_changed(self.ambient_pressure, pascals, threshold_pa) {
+            return Ok(false);
+        }
+
+        self.set_ambient_pressure(pascals).await?;
+        Ok(true)
+    }
+
+    /// Performs forced recalibration (FRC) to the given CO2 reference value.
+    /// Periodic measurement must have been stopped since the last reinit,
+    /// factory reset or power-down, see [`super::SCD4x::perform_forced_recalibration`].
+    /// Returns the FRC correction in ppm.
+    pub async fn perform_forced_recalibration(
+        &mut self,
+        target_ppm: u16,
+    ) -> Result<i16, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        check_stopped_from_periodic(self.stopped_from_periodic)?;
+        let [response] = self
+            .sensor
+            .command_with_args_read(&commands::PERFORM_FORCED_RECALIBRATION, &[target_ppm])
+            .await?;
+
+        decode_forced_recalibration(response)
+    }
+
+    async fn ensure_asc_periods_supported(&mut self) -> Result<(), Error<I2C::Error>> {
+        let variant = self.sensor_variant().await?;
+        check_asc_periods_supported(variant)
+    }
+
+    /// Enables or disables automatic self-calibration.
+    pub async fn set_automatic_self_calibration_enabled(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .write_command_with_args(
+                &commands::SET_AUTOMATIC_SELF_CALIBRATION_ENABLED,
+                &[enabled as u16],
+            )
+            .await
+    }
+
+    /// Returns true if automatic self-calibration is enabled.
+    pub async fn get_automatic_self_calibration_enabled(
+        &mut self,
+    ) -> Result<bool, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let status = self
+            .sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_ENABLED)
+            .await?;
+
+        decode_asc_enabled(status)
+    }
+
+    /// Sets the CO2 value in ppm that automatic self-calibration uses as the baseline.
+    pub async fn set_automatic_self_calibration_target(
+        &mut self,
+        target_ppm: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .write_command_with_args(
+                &commands::SET_AUTOMATIC_SELF_CALIBRATION_TARGET,
+                &[target_ppm],
+            )
+            .await
+    }
+
+    /// Reads out the automatic self-calibration target in ppm.
+    pub async fn get_automatic_self_calibration_target(
+        &mut self,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_TARGET)
+            .await
+    }
+
+    /// Sets the duration of the initial automatic self-calibration period in hours.
+    /// Must be a multiple of 4 hours. Not supported by SCD40.
+    pub async fn set_automatic_self_calibration_initial_period(
+        &mut self,
+        hours: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let hours = check_asc_period(hours)?;
+        self.ensure_asc_periods_supported().await?;
+
+        self.sensor
+            .write_command_with_args(
+                &commands::SET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD,
+                &[hours],
+            )
+            .await
+    }
+
+    /// Reads out the duration of the initial automatic self-calibration period in hours.
+    /// Not supported by SCD40.
+    pub async fn get_automatic_self_calibration_initial_period(
+        &mut self,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.ensure_asc_periods_supported().await?;
+
+        self.sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD)
+            .await
+    }
+
+    /// Sets the duration of the standard automatic self-calibration period in hours.
+    /// Must be a multiple of 4 hours. Not supported by SCD40.
+    pub async fn set_automatic_self_calibration_standard_period(
+        &mut self,
+        hours: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        let hours = check_asc_period(hours)?;
+        self.ensure_asc_periods_supported().await?;
+
+        self.sensor
+            .write_command_with_args(
+                &commands::SET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD,
+                &[hours],
+            )
+            .await
+    }
+
+    /// Reads out the duration of the standard automatic self-calibration period in hours.
+    /// Not supported by SCD40.
+    pub async fn get_automatic_self_calibration_standard_period(
+        &mut self,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.ensure_asc_periods_supported().await?;
+
+        self.sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD)
+            .await
+    }
+
+    /// Reads out the complete automatic self-calibration configuration.
+    pub async fn get_asc_config(&mut self) -> Result<AscConfig, Error<I2C::Error>> {
+        let variant = self.sensor_variant().await?;
+        let words = self
+            .read_config_words(ConfigWords::layout(variant, false).asc_only())
+            .await?;
+
+        words.decode_asc()
+    }
+
+    /// Writes the complete automatic self-calibration configuration.
+    /// Periods set to `None` are left unchanged. Nothing is written if `config`
+    /// is invalid or sets periods on a variant that does not support them.
+    pub async fn set_asc_config(&mut self, config: &AscConfig) -> Result<(), Error<I2C::Error>> {
+        check_asc_config(config)?;
+        self.state.ensure_idle()?;
+        if config.initial_period_hours.is_some() || config.standard_period_hours.is_some() {
+            self.ensure_asc_periods_supported().await?;
+        }
+
+        self.set_automatic_self_calibration_enabled(config.enabled)
+            .await?;
+        self.set_automatic_self_calibration_target(config.target_ppm)
+            .await?;
+        if let Some(hours) = config.initial_period_hours {
+            self.set_automatic_self_calibration_initial_period(hours)
+                .await?;
+        }
+        if let Some(hours) = config.standard_period_hours {
+            self.set_automatic_self_calibration_standard_period(hours)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn read_config_words(
+        &mut self,
+        layout: ConfigWords,
+    ) -> Result<ConfigWords, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+
+        let mut words = layout;
+        for (index, cmd) in layout.getters() {
+            words.0[index] = Some(self.sensor.one_word_command(cmd).await?);
+        }
+
+        Ok(words)
+    }
+
+    /// Writes the words that differ from `current`. Returns true if any word was written.
+    async fn write_config_words(
+        &mut self,
+        words: &ConfigWords,
+        current: &ConfigWords,
+    ) -> Result<bool, Error<I2C::Error>> {
+        let mut changed = false;
+        for (cmd, word) in words.changes(current) {
+            self.sensor.write_command_with_args(cmd, &[word]).await?;
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    /// Reads out the complete sensor configuration. See [`super::SCD4x::read_config`].
+    pub async fn read_config(&mut self) -> Result<Scd4xConfig, Error<I2C::Error>> {
+        let variant = self.sensor_variant().await?;
+        let layout = ConfigWords::layout(variant, self.ambient_pressure.is_some());
+        let words = self.read_config_words(layout).await?;
+
+        words.decode()
+    }
+
+    /// Writes the settings from `config` that differ from the current sensor
+    /// configuration. See [`super::SCD4x::apply_config`].
+    /// Returns true if any setting was written.
+    pub async fn apply_config(&mut self, config: &Scd4xConfig) -> Result<bool, Error<I2C::Error>> {
+        let words = ConfigWords::encode(config)?;
+        self.state.ensure_idle()?;
+        let variant = self.sensor_variant().await?;
+        words.check_supported(variant)?;
+
+        let layout = ConfigWords::layout(variant, config.ambient_pressure_pa.is_some());
+        let current = self.read_config_words(layout).await?;
+        let changed = self.write_config_words(&words, &current).await?;
+        if config.ambient_pressure_pa.is_some() {
+            self.ambient_pressure = config.ambient_pressure_pa;
+        }
+
+        Ok(changed)
+    }
+
+    /// Stores the current configuration in the EEPROM, unless it matches the
+    /// stored one. See [`super::SCD4x::persist_settings`].
+    /// Returns true if the EEPROM was written.
+    pub async fn persist_settings(&mut self) -> Result<bool, Error<I2C::Error>> {
+        let layout = ConfigWords::persistent(self.sensor_variant().await?);
+        let current = self.read_config_words(layout).await?;
+        let ambient_pressure = self.ambient_pressure;
+
+        self.reinit().await?;
+        if let Some(pascals) = ambient_pressure {
+            self.set_ambient_pressure(pascals).await?;
+        }
+        if self.read_config_words(layout).await? == current {
+            return Ok(false);
+        }
+
+        self.write_config_words(&current, &ConfigWords::default())
+            .await?;
+        self.sensor
+            .send_command(&commands::PERSIST_SETTINGS)
+            .await?;
+        Ok(true)
+    }
+
+    /// Reinitializes the sensor by reloading user settings from the EEPROM.
+    /// The ambient pressure set with set_ambient_pressure is lost.
+    pub async fn reinit(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor.send_command(&commands::REINIT).await?;
+        self.ambient_pressure = None;
+        self.stopped_from_periodic = false;
+        Ok(())
+    }
+
+    /// Resets all configuration settings stored in the EEPROM and erases the
+    /// FRC and ASC algorithm history.
+    pub async fn perform_factory_reset(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor
+            .send_command(&commands::PERFORM_FACTORY_RESET)
+            .await?;
+        self.ambient_pressure = None;
+        self.stopped_from_periodic = false;
+        Ok(())
+    }
+
+    async fn ensure_single_shot_supported(&mut self) -> Result<(), Error<I2C::Error>> {
+        let variant = self.sensor_variant().await?;
+        check_single_shot_supported(variant)
+    }
+
+    /// Performs an on-demand measurement of CO2 concentration, relative humidity
+    /// and temperature, and reads it out. Takes 5 seconds. Not supported by SCD40.
+    pub async fn measure_single_shot(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.ensure_single_shot_supported().await?;
+
+        self.sensor
+            .send_command(&commands::MEASURE_SINGLE_SHOT)
+            .await?;
+        self.read_measurement().await
+    }
+
+    /// Performs an on-demand measurement of relative humidity and temperature
+    /// only, and reads it out. Takes 50 ms. Not supported by SCD40.
+    pub async fn measure_single_shot_rht_only(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.ensure_single_shot_supported().await?;
+
+        self.sensor
+            .send_command(&commands::MEASURE_SINGLE_SHOT_RHT_ONLY)
+            .await?;
+        Ok(Measurement {
+            co2_ppm: None,
+            ..self.read_measurement().await?
+        })
+    }
+
+    /// Puts the sensor from idle to sleep to reduce current consumption.
+    pub async fn power_down(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()?;
+        self.sensor.send_command(&commands::POWER_DOWN).await?;
+        self.state = State::PoweredDown;
+        self.stopped_from_periodic = false;
+        Ok(())
+    }
+
+    /// Wakes up the sensor from sleep mode into idle mode. The sensor does not
+    /// acknowledge the wake_up command, so a NACK is not treated as an error.
+    pub async fn wake_up(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_can_wake_up()?;
+
+        // Waits even if the command was not acknowledged.
+        ignore_nack(self.sensor.start_command(&commands::WAKE_UP).await)?;
+        self.sensor.delay_ms(commands::WAKE_UP.exec_time_ms).await;
+        self.state = State::Idle;
+        Ok(())
+    }
+
+    /// Wakes up the sensor and verifies that it is responsive by reading out
+    /// the serial number.
+    pub async fn wake_up_and_verify(&mut self) -> Result<u64, Error<I2C::Error>> {
+        self.wake_up().await?;
+        self.get_serial_number().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SCD4x;
+    use crate::test_support::{DummyBus, MockBus, RecordingDelay};
+    use embassy_futures::block_on;
+
+    #[test]
+    fn test_read_measurement() {
+        let mut bus = MockBus::new(&[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay);
+
+        let result = block_on(sensor.read_measurement());
+        assert!(matches!(result, Ok(m) if m.co2_ppm == Some(500)));
+
+        assert_eq!(bus.written, [0xec, 0x05]);
+        assert_eq!(delay.total_ns, 1_000_000);
+    }
+
+    #[test]
+    fn test_perform_forced_recalibration() {
+        let mut bus = MockBus::new(&[0x7f, 0xce, 0x7b]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay);
+
+        assert_eq!(
+            block_on(sensor.perform_forced_recalibration(480)),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(block_on(sensor.start_periodic_measurement()), Ok(()));
+        assert_eq!(block_on(sensor.stop_periodic_measurement()), Ok(()));
+        assert_eq!(block_on(sensor.perform_forced_recalibration(480)), Ok(-50));
+
+        assert_eq!(
+            bus.written,
+            [0x21, 0xb1, 0x3f, 0x86, 0x36, 0x2f, 0x01, 0xe0, 0xb4]
+        );
+        // 500 ms stop and 400 ms FRC.
+        assert_eq!(delay.total_ns, 900_000_000);
+    }
+
+    #[test]
+    fn test_set_asc_config_unsupported() {
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]); // SCD40
+        let mut sensor = SCD4x::new(&mut bus, RecordingDelay::default());
+
+        let config = super::AscConfig {
+            enabled: true,
+            target_ppm: 400,
+            initial_period_hours: None,
+            standard_period_hours: Some(156),
+        };
+        assert_eq!(
+            block_on(sensor.set_asc_config(&config)),
+            Err(super::Error::Unsupported)
+        );
+        assert_eq!(bus.written, [0x20, 0x2f]);
+    }
+
+    #[test]
+    fn test_powered_down_rejects_commands() {
+        let mut bus = MockBus::new(&[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c]);
+        let mut sensor = SCD4x::new(&mut bus, RecordingDelay::default());
+
+        assert_eq!(block_on(sensor.power_down()), Ok(()));
+        assert!(matches!(
+            block_on(sensor.read_measurement()),
+            Err(super::Error::InvalidState)
+        ));
+        assert_eq!(
+            block_on(sensor.set_ambient_pressure(101_300)),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(bus.written, [0x36, 0xe0]);
+    }
+
+    #[test]
+    fn test_measure_single_shot() {
+        let mut bus = MockBus::sequence(&[
+            &[0x14, 0x40, 0x51], // SCD41
+            &[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
+        ]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay);
+
+        let result = block_on(sensor.measure_single_shot());
+        assert!(matches!(result, Ok(m) if m.co2_ppm == Some(500)));
+        assert_eq!(bus.written, [0x20, 0x2f, 0x21, 0x9d, 0xec, 0x05]);
+        // 1 ms variant query, 5000 ms measurement and 1 ms readout.
+        assert_eq!(delay.total_ns, 5_002_000_000);
+    }
+
+    #[test]
+    fn test_measure_single_shot_rht_only() {
+        let mut bus = MockBus::new(&[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c]);
+        let mut sensor =
+            SCD4x::new(&mut bus, RecordingDelay::default()).with_variant(super::Variant::SCD43);
+
+        let result = block_on(sensor.measure_single_shot_rht_only());
+        assert!(matches!(result, Ok(m) if m.co2_ppm.is_none()));
+        assert_eq!(bus.written, [0x21, 0x96, 0xec, 0x05]);
+    }
+
+    #[test]
+    fn test_configuration_while_measuring() {
+        let bus = DummyBus { response: &[] };
+        let mut sensor = SCD4x::new(bus, RecordingDelay::default());
+
+        assert_eq!(block_on(sensor.start_periodic_measurement()), Ok(()));
+        assert_eq!(
+            block_on(sensor.set_sensor_altitude(100)),
+            Err(super::Error::InvalidState)
+        );
+    }
+
+    #[test]
+    fn test_apply_config() {
+        // Every read returns zero: offset 0, altitude 0, pressure 0, ASC off, SCD40.
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay);
+
+        let config = super::Scd4xConfig {
+            temperature_offset_celsius: 0.0,
+            sensor_altitude_meters: 0,
+            ambient_pressure_pa: Some(101300),
+            asc: super::AscConfig {
+                enabled: false,
+                target_ppm: 400,
+                initial_period_hours: None,
+                standard_period_hours: None,
+            },
+        };
+        assert_eq!(block_on(sensor.apply_config(&config)), Ok(true));
+        assert_eq!(
+            bus.written,
+            [
+                0x20, 0x2f, 0x23, 0x18, 0x23, 0x22, 0xe0, 0x00, 0x23, 0x13, 0x23,
+                0x3f, // reads
+                0xe0, 0x00, 0x03, 0xf5, 0xdb, // set ambient pressure
+                0x24, 0x3a, 0x01, 0x90, 0x4c, // set ASC target
+            ]
+        );
+        assert_eq!(delay.total_ns, 8_000_000);
+    }
+
+    #[test]
+    fn test_persist_settings() {
+        let mut bus = MockBus::sequence(&[
+            // Current settings: offset 0x0ccc, altitude 100, ASC on, target 400.
+            &[0x0c, 0xcc, 0x0f],
+            &[0x00, 0x64, 0xfe],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0x90, 0x4c],
+            // Stored settings differ in the temperature offset.
+            &[0x00, 0x00, 0x81],
+            &[0x00, 0x64, 0xfe],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0x90, 0x4c],
+        ]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay).with_variant(super::Variant::SCD40);
+
+        assert_eq!(block_on(sensor.persist_settings()), Ok(true));
+        assert_eq!(
+            bus.written,
+            [
+                0x23, 0x18, 0x23, 0x22, 0x23, 0x13, 0x23, 0x3f, // read current settings
+                0x36, 0x46, // reinit
+                0x23, 0x18, 0x23, 0x22, 0x23, 0x13, 0x23, 0x3f, // read stored settings
+                0x24, 0x1d, 0x0c, 0xcc, 0x0f, // set temperature offset
+                0x24, 0x27, 0x00, 0x64, 0xfe, // set sensor altitude
+                0x24, 0x16, 0x00, 0x01, 0xb0, // set ASC enabled
+                0x24, 0x3a, 0x01, 0x90, 0x4c, // set ASC target
+                0x36, 0x15, // persist settings
+            ]
+        );
+        assert_eq!(delay.total_ns, 842_000_000);
+    }
+}
@@ -1,43 +1,45 @@
 use crate::sensirion::Cmd;
 
 // Basic commands
-pub const START_PERIODIC_MEASUREMENTS: Cmd = [0x21, 0xb1];
-pub const READ_MEASUREMENT: Cmd = [0xec, 0x05];
-pub const STOP_PERIODIC_MEASUREMENTS: Cmd = [0x3f, 0x86];
+pub const START_PERIODIC_MEASUREMENTS: Cmd = Cmd::new([0x21, 0xb1], 0);
+pub const READ_MEASUREMENT: Cmd = Cmd::new([0xec, 0x05], 1);
+pub const STOP_PERIODIC_MEASUREMENTS: Cmd = Cmd::new([0x3f, 0x86], 500);
 
 // On-chip output signal compensation
-pub const SET_TEMPERATURE_OFFSET: Cmd = [0x24, 0x1d];
-pub const GET_TEMPERATURE_OFFSET: Cmd = [0x23, 0x18];
-pub const SET_SENSOR_ALTITUDE: Cmd = [0x24, 0x27];
-pub const GET_SENSOR_ALTITUDE: Cmd = [0x23, 0x22];
-pub const SET_AMBIENT_PRESSURE: Cmd = [0xe0, 0x00];
-pub const GET_AMBIENT_PRESSURE: Cmd = [0xe0, 0x00];
+pub const SET_TEMPERATURE_OFFSET: Cmd = Cmd::new([0x24, 0x1d], 1);
+pub const GET_TEMPERATURE_OFFSET: Cmd = Cmd::new([0x23, 0x18], 1);
+pub const SET_SENSOR_ALTITUDE: Cmd = Cmd::new([0x24, 0x27], 1);
+pub const GET_SENSOR_ALTITUDE: Cmd = Cmd::new([0x23, 0x22], 1);
+pub const SET_AMBIENT_PRESSURE: Cmd = Cmd::new([0xe0, 0x00], 1);
+pub const GET_AMBIENT_PRESSURE: Cmd = Cmd::new([0xe0, 0x00], 1);
 
 // Field calibration
-pub const PERFORM_FORCED_RECALIBRATION: Cmd = [0x36, 0x2f];
-pub const SET_AUTOMATIC_SELF_CALIBRATION_ENABLED: Cmd = [0x24, 0x16];
-pub const GET_AUTOMATIC_SELF_CALIBRATION_ENABLED: Cmd = [0x23, 0x13];
-pub const SET_AUTOMATIC_SELF_CALIBRATION_TARGET: Cmd = [0x24, 0x3a];
-pub const GET_AUTOMATIC_SELF_CALIBRATION_TARGET: Cmd = [0x23, 0x3f];
+pub const PERFORM_FORCED_RECALIBRATION: Cmd = Cmd::new([0x36, 0x2f], 400);
+pub const SET_AUTOMATIC_SELF_CALIBRATION_ENABLED: Cmd = Cmd::new([0x24, 0x16], 1);
+pub const GET_AUTOMATIC_SELF_CALIBRATION_ENABLED: Cmd = Cmd::new([0x23, 0x13], 1);
+pub const SET_AUTOMATIC_SELF_CALIBRATION_TARGET: Cmd = Cmd::new([0x24, 0x3a], 1);
+pub const GET_AUTOMATIC_SELF_CALIBRATION_TARGET: Cmd = Cmd::new([0x23, 0x3f], 1);
 
 // Low power periodic measurement mode
-pub const START_LOW_POWER_PERIODIC_MEASUREMENT: Cmd = [0x21, 0xac];
-pub const GET_DATA_READY_STATUS: Cmd = [0xe4, 0xb8];
+pub const START_LOW_POWER_PERIODIC_MEASUREMENT: Cmd = Cmd::new([0x21, 0xac], 0);
+pub const GET_DATA_READY_STATUS: Cmd = Cmd::new([0xe4, 0xb8], 1);
 
 // Advanced features
-pub const PERSIST_SETTINGS: Cmd = [0x36, 0x15];
-pub const GET_SERIAL_NUMBER: Cmd = [0x36, 0x82];
-pub const PERFORM_SELF_TEST: Cmd = [0x36, 0x39];
-pub const PERFORM_FACTORY_RESET: Cmd = [0x36, 0x32];
-pub const REINIT: Cmd = [0x36, 0x46];
-pub const GET_SENSOR_VARIANT: Cmd = [0x20, 0x2f];
+pub const PERSIST_SETTINGS: Cmd = Cmd::new([0x36, 0x15], 800);
+pub const GET_SERIAL_NUMBER: Cmd = Cmd::new([0x36, 0x82], 1);
+pub const PERFORM_SELF_TEST: Cmd = Cmd::new([0x36, 0x39], 10000);
+pub const PERFORM_FACTORY_RESET: Cmd = Cmd::new([0x36, 0x32], 1200);
+pub const REINIT: Cmd = Cmd::new([0x36, 0x46], 30);
+pub const GET_SENSOR_VARIANT: Cmd = Cmd::new([0x20, 0x2f], 1);
 
 // Single shot measurement mode (SCD41 and SCD43)
-pub const MEASURE_SINGLE_SHOT: Cmd = [0x21, 0x9d];
-pub const MEASURE_SINGLE_SHOT_RHT_ONLY: Cmd = [0x21, 0x96];
-pub const POWER_UP: Cmd = [0x36, 0xe0];
-pub const WAKE_UP: Cmd = [0x36, 0xf6];
-pub const SET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD: Cmd = [0x24, 0x45];
-pub const GET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD: Cmd = [0x23, 0x40];
-pub const SET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD: Cmd = [0x24, 0x4e];
-pub const GET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD: Cmd = [0x23, 0x4b];
+pub const MEASURE_SINGLE_SHOT: Cmd = Cmd::new([0x21, 0x9d], 5000);
+pub const MEASURE_SINGLE_SHOT_RHT_ONLY: Cmd = Cmd::new([0x21, 0x96], 50);
+pub const POWER_DOWN: Cmd = Cmd::new([0x36, 0xe0], 1);
+#[deprecated(note = "the datasheet name of this command is power_down, use POWER_DOWN")]
+pub const POWER_UP: Cmd = POWER_DOWN;
+pub const WAKE_UP: Cmd = Cmd::new([0x36, 0xf6], 30);
+pub const SET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD: Cmd = Cmd::new([0x24, 0x45], 1);
+pub const GET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD: Cmd = Cmd::new([0x23, 0x40], 1);
+pub const SET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD: Cmd = Cmd::new([0x24, 0x4e], 1);
+pub const GET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD: Cmd = Cmd::new([0x23, 0x4b], 1);
@@ -1,12 +1,30 @@
 use core::fmt;
+use core::marker::PhantomData;
+use embedded_hal::delay::DelayNs;
 use embedded_hal::i2c::I2c;
 
 use crate::sensirion::*;
 
+#[cfg(feature = "async")]
+pub mod asynch;
 pub mod commands;
+pub mod mode;
+
+use mode::{
+    Awake, Configurable, Dynamic, Idle, LowPowerPeriodic, Measuring, Mode, Periodic, PoweredDown,
+};
 
 const ADDR: u8 = 0x62;
 
+// Datasheet recommends keeping the temperature offset within 0..20°C.
+const MAX_TEMPERATURE_OFFSET: f32 = 20.0;
+const MAX_SENSOR_ALTITUDE: u16 = 3000;
+const AMBIENT_PRESSURE_RANGE: core::ops::RangeInclusive<u32> = 70_000..=120_000;
+// ASC periods must be integer multiples of 4 hours.
+const ASC_PERIOD_STEP_HOURS: u16 = 4;
+// Interval between data ready polls in wait_for_measurement.
+const DATA_READY_POLL_INTERVAL_MS: u32 = 100;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Variant {
     SCD40,
@@ -14,195 +32,1269 @@ pub enum Variant {
     SCD43,
 }
 
+impl Variant {
+    /// Returns true if the variant supports single shot measurements.
+    pub fn supports_single_shot(&self) -> bool {
+        matches!(self, Variant::SCD41 | Variant::SCD43)
+    }
+
+    /// Returns true if the variant supports configuring the ASC initial and standard periods.
+    pub fn supports_asc_periods(&self) -> bool {
+        matches!(self, Variant::SCD41 | Variant::SCD43)
+    }
+
+    /// Returns the datasheet CO2 accuracy specification.
+    pub fn co2_accuracy(&self) -> Co2Accuracy {
+        match self {
+            Variant::SCD40 => Co2Accuracy {
+                min_ppm: 400,
+                max_ppm: 2000,
+                offset_ppm: 50,
+                reading_percent: 5,
+            },
+            Variant::SCD41 => Co2Accuracy {
+                min_ppm: 400,
+                max_ppm: 5000,
+                offset_ppm: 40,
+                reading_percent: 5,
+            },
+            Variant::SCD43 => Co2Accuracy {
+                min_ppm: 400,
+                max_ppm: 5000,
+                offset_ppm: 30,
+                reading_percent: 3,
+            },
+        }
+    }
+}
+
 impl fmt::Display for Variant {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+/// CO2 accuracy specification, valid from 15 °C to 35 °C and from 20 %RH to 65 %RH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Co2Accuracy {
+    /// Lower bound of the range the accuracy is specified for.
+    pub min_ppm: u16,
+    /// Upper bound of the range the accuracy is specified for.
+    pub max_ppm: u16,
+    /// Fixed part of the tolerance.
+    pub offset_ppm: u16,
+    /// Part of the tolerance proportional to the reading.
+    pub reading_percent: u16,
+}
+
+impl Co2Accuracy {
+    /// Returns the tolerance in ppm for the given reading.
+    pub fn tolerance_ppm(&self, reading_ppm: u16) -> u16 {
+        self.offset_ppm + (reading_ppm as u32 * self.reading_percent as u32 / 100) as u16
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Measurement {
-    pub co2_ppm: u16,
+    /// CO2 concentration, `None` for temperature and humidity only measurements.
+    pub co2_ppm: Option<u16>,
     pub temp_celsius: f32,
     pub humidity_percent: f32,
 }
 
+impl Measurement {
+    fn from_words(words: &[u16; 3]) -> Self {
+        Self {
+            co2_ppm: Some(words[0]),
+            temp_celsius: -45.0 + 175.0 * (words[1] as f32 / 65535.0),
+            humidity_percent: 100.0 * words[2] as f32 / 65535.0,
+        }
+    }
+}
+
 impl fmt::Display for Measurement {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(co2_ppm) = self.co2_ppm {
+            write!(f, "{} ppm CO2, ", co2_ppm)?;
+        }
+        write!(
+            f,
+            "{:.1}°C, {:.1}% RH",
+            self.temp_celsius, self.humidity_percent
+        )
+    }
+}
+
+/// Measurement as the raw words sent by the sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RawMeasurement {
+    pub co2_ppm: u16,
+    pub temperature_ticks: u16,
+    pub humidity_ticks: u16,
+}
+
+impl RawMeasurement {
+    fn from_words(words: &[u16; 3]) -> Self {
+        Self {
+            co2_ppm: words[0],
+            temperature_ticks: words[1],
+            humidity_ticks: words[2],
+        }
+    }
+
+    fn words(&self) -> [u16; 3] {
+        [self.co2_ppm, self.temperature_ticks, self.humidity_ticks]
+    }
+
+    /// Converts the raw words to a floating point measurement.
+    pub fn to_measurement(&self) -> Measurement {
+        Measurement::from_words(&self.words())
+    }
+
+    /// Converts the raw words to a fixed-point measurement.
+    pub fn to_fixed(&self) -> FixedMeasurement {
+        FixedMeasurement::from_words(&self.words())
+    }
+}
+
+/// Measurement converted with integer math only, for targets without an FPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FixedMeasurement {
+    /// CO2 concentration, `None` for temperature and humidity only measurements.
+    pub co2_ppm: Option<u16>,
+    pub temp_milli_celsius: i32,
+    pub humidity_milli_percent: u32,
+}
+
+impl FixedMeasurement {
+    fn from_words(words: &[u16; 3]) -> Self {
+        Self {
+            co2_ppm: Some(words[0]),
+            temp_milli_celsius: milli_celsius(words[1]),
+            humidity_milli_percent: milli_percent_rh(words[2]),
+        }
+    }
+}
+
+impl fmt::Display for FixedMeasurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(co2_ppm) = self.co2_ppm {
+            write!(f, "{} ppm CO2, ", co2_ppm)?;
+        }
+        let sign = if self.temp_milli_celsius < 0 { "-" } else { "" };
+        // Round to tenths, like `{:.1}` does for the floating point measurement.
+        let temp = (self.temp_milli_celsius.unsigned_abs() + 50) / 100;
+        let humidity = (self.humidity_milli_percent + 50) / 100;
         write!(
             f,
-            "{} ppm CO2, {:.1}°C, {:.1}% RH",
-            self.co2_ppm, self.temp_celsius, self.humidity_percent
+            "{}{}.{}°C, {}.{}% RH",
+            sign,
+            temp / 10,
+            temp % 10,
+            humidity / 10,
+            humidity % 10
         )
     }
 }
 
+/// Automatic self-calibration (ASC) configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AscConfig {
+    pub enabled: bool,
+    pub target_ppm: u16,
+    /// Duration of the initial ASC period, `None` on SCD40 which does not
+    /// support configuring it.
+    pub initial_period_hours: Option<u16>,
+    /// Duration of the standard ASC period, `None` on SCD40 which does not
+    /// support configuring it.
+    pub standard_period_hours: Option<u16>,
+}
+
+/// Snapshot of the sensor configuration, see read_config and apply_config.
+/// Configurations compare equal if they encode to the same sensor words.
+#[derive(Debug, Clone, Copy)]
+pub struct Scd4xConfig {
+    pub temperature_offset_celsius: f32,
+    pub sensor_altitude_meters: u16,
+    /// Ambient pressure used for compensation, `None` to leave it unchanged,
+    /// e.g. when the sensor altitude is used for compensation instead.
+    pub ambient_pressure_pa: Option<u32>,
+    pub asc: AscConfig,
+}
+
+impl PartialEq for Scd4xConfig {
+    fn eq(&self, other: &Self) -> bool {
+        temperature_offset_ticks(self.temperature_offset_celsius)
+            == temperature_offset_ticks(other.temperature_offset_celsius)
+            && self.sensor_altitude_meters == other.sensor_altitude_meters
+            && self.ambient_pressure_pa.map(ambient_pressure_ticks)
+                == other.ambient_pressure_pa.map(ambient_pressure_ticks)
+            && self.asc == other.asc
+    }
+}
+
+/// Result of the sensor self-test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SelfTestReport {
+    /// Raw status word reported by the sensor, 0 if no malfunction was detected.
+    pub malfunction: u16,
+}
+
+impl SelfTestReport {
+    /// Returns true if no malfunction was detected.
+    pub fn passed(&self) -> bool {
+        self.malfunction == 0
+    }
+}
+
+/// Getter and setter of each configuration word, in the order of [`ConfigWords`].
+static CONFIG_COMMANDS: [(Cmd, Cmd); 7] = [
+    (
+        commands::GET_TEMPERATURE_OFFSET,
+        commands::SET_TEMPERATURE_OFFSET,
+    ),
+    (commands::GET_SENSOR_ALTITUDE, commands::SET_SENSOR_ALTITUDE),
+    (
+        commands::GET_AMBIENT_PRESSURE,
+        commands::SET_AMBIENT_PRESSURE,
+    ),
+    (
+        commands::GET_AUTOMATIC_SELF_CALIBRATION_ENABLED,
+        commands::SET_AUTOMATIC_SELF_CALIBRATION_ENABLED,
+    ),
+    (
+        commands::GET_AUTOMATIC_SELF_CALIBRATION_TARGET,
+        commands::SET_AUTOMATIC_SELF_CALIBRATION_TARGET,
+    ),
+    (
+        commands::GET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD,
+        commands::SET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD,
+    ),
+    (
+        commands::GET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD,
+        commands::SET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD,
+    ),
+];
+
+/// Configuration as the raw words exchanged with the sensor, `None` for words
+/// that are not read or written. The drivers only sequence the I2C commands,
+/// validation, encoding and diffing are done here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct ConfigWords([Option<u16>; CONFIG_COMMANDS.len()]);
+
+impl ConfigWords {
+    const TEMPERATURE_OFFSET: usize = 0;
+    const SENSOR_ALTITUDE: usize = 1;
+    const AMBIENT_PRESSURE: usize = 2;
+    const ASC_ENABLED: usize = 3;
+    const ASC_TARGET: usize = 4;
+    const ASC_INITIAL_PERIOD: usize = 5;
+    const ASC_STANDARD_PERIOD: usize = 6;
+
+    /// Words to read out on `variant`. ASC periods are only included on
+    /// variants that support them.
+    fn layout(variant: Variant, ambient_pressure: bool) -> Self {
+        let mut words = [Some(0); CONFIG_COMMANDS.len()];
+        if !ambient_pressure {
+            words[Self::AMBIENT_PRESSURE] = None;
+        }
+        if !variant.supports_asc_periods() {
+            words[Self::ASC_INITIAL_PERIOD] = None;
+            words[Self::ASC_STANDARD_PERIOD] = None;
+        }
+
+        Self(words)
+    }
+
+    /// Words stored in the EEPROM by persist_settings.
+    fn persistent(variant: Variant) -> Self {
+        Self::layout(variant, false)
+    }
+
+    /// Keeps only the automatic self-calibration words.
+    fn asc_only(mut self) -> Self {
+        self.0[..Self::ASC_ENABLED].fill(None);
+        self
+    }
+
+    /// Validates and encodes `config`. ASC periods set to `None` are left out.
+    fn encode<E>(config: &Scd4xConfig) -> Result<Self, Error<E>> {
+        check_asc_config(&config.asc)?;
+
+        let mut words = [None; CONFIG_COMMANDS.len()];
+        words[Self::TEMPERATURE_OFFSET] = Some(encode_temperature_offset(
+            config.temperature_offset_celsius,
+        )?);
+        words[Self::SENSOR_ALTITUDE] = Some(check_sensor_altitude(config.sensor_altitude_meters)?);
+        words[Self::AMBIENT_PRESSURE] = config
+            .ambient_pressure_pa
+            .map(encode_ambient_pressure)
+            .transpose()?;
+        words[Self::ASC_ENABLED] = Some(config.asc.enabled as u16);
+        words[Self::ASC_TARGET] = Some(config.asc.target_ppm);
+        words[Self::ASC_INITIAL_PERIOD] = config.asc.initial_period_hours;
+        words[Self::ASC_STANDARD_PERIOD] = config.asc.standard_period_hours;
+
+        Ok(Self(words))
+    }
+
+    /// Fails if the words include settings `variant` does not support, so that
+    /// apply_config is rejected before anything is written.
+    fn check_supported<E>(&self, variant: Variant) -> Result<(), Error<E>> {
+        if self.0[Self::ASC_INITIAL_PERIOD].is_some() || self.0[Self::ASC_STANDARD_PERIOD].is_some()
+        {
+            check_asc_periods_supported(variant)?;
+        }
+
+        Ok(())
+    }
+
+    fn word(&self, index: usize) -> u16 {
+        self.0[index].unwrap_or_default()
+    }
+
+    fn decode<E>(&self) -> Result<Scd4xConfig, Error<E>> {
+        Ok(Scd4xConfig {
+            temperature_offset_celsius: decode_temperature_offset(
+                self.word(Self::TEMPERATURE_OFFSET),
+            ),
+            sensor_altitude_meters: self.word(Self::SENSOR_ALTITUDE),
+            ambient_pressure_pa: self.0[Self::AMBIENT_PRESSURE].map(decode_ambient_pressure),
+            asc: self.decode_asc()?,
+        })
+    }
+
+    fn decode_asc<E>(&self) -> Result<AscConfig, Error<E>> {
+        Ok(AscConfig {
+            enabled: decode_asc_enabled(self.word(Self::ASC_ENABLED))?,
+            target_ppm: self.word(Self::ASC_TARGET),
+            initial_period_hours: self.0[Self::ASC_INITIAL_PERIOD],
+            standard_period_hours: self.0[Self::ASC_STANDARD_PERIOD],
+        })
+    }
+
+    /// Getters of the words to read out.
+    fn getters(self) -> impl Iterator<Item = (usize, &'static Cmd)> {
+        CONFIG_COMMANDS
+            .iter()
+            .zip(self.0)
+            .enumerate()
+            .filter_map(|(index, ((get, _), word))| word.map(|_| (index, get)))
+    }
+
+    /// Setters and words that differ from `current`.
+    fn changes<'a>(&'a self, current: &'a Self) -> impl Iterator<Item = (&'static Cmd, u16)> + 'a {
+        CONFIG_COMMANDS
+            .iter()
+            .zip(self.0.iter().zip(current.0))
+            .filter_map(|((_, set), (word, current))| match *word {
+                Some(word) if current != Some(word) => Some((set, word)),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Periodic,
+    LowPowerPeriodic,
+    PoweredDown,
+}
+
+impl State {
+    /// Configuration commands are only accepted while the sensor is idle.
+    fn ensure_idle<E>(self) -> Result<(), Error<E>> {
+        if self == State::Idle {
+            Ok(())
+        } else {
+            Err(Error::InvalidState)
+        }
+    }
+
+    /// Only wake_up is accepted while the sensor is powered down.
+    fn ensure_awake<E>(self) -> Result<(), Error<E>> {
+        if self == State::PoweredDown {
+            Err(Error::InvalidState)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn ensure_can_wake_up<E>(self) -> Result<(), Error<E>> {
+        if matches!(self, State::Idle | State::PoweredDown) {
+            Ok(())
+        } else {
+            Err(Error::InvalidState)
+        }
+    }
+}
+
+// Conversions shared by the blocking and async drivers.
+
+fn decode_data_ready_status(status: u16) -> bool {
+    // From the datasheet, if the 11 LSB are 0, data is not ready.
+    (status & 0x7FF) != 0
+}
+
+fn decode_sensor_variant<E>(status: u16) -> Result<Variant, Error<E>> {
+    match status >> 12 {
+        0b0000 => Ok(Variant::SCD40),
+        0b0001 => Ok(Variant::SCD41),
+        0b0101 => Ok(Variant::SCD43),
+        _ => Err(Error::InvalidResponse),
+    }
+}
+
+fn temperature_offset_ticks(celsius: f32) -> u16 {
+    (celsius * 65535.0 / 175.0 + 0.5) as u16
+}
+
+fn encode_temperature_offset<E>(celsius: f32) -> Result<u16, Error<E>> {
+    if !(0.0..=MAX_TEMPERATURE_OFFSET).contains(&celsius) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(temperature_offset_ticks(celsius))
+}
+
+fn decode_temperature_offset(ticks: u16) -> f32 {
+    ticks as f32 * 175.0 / 65535.0
+}
+
+fn check_sensor_altitude<E>(meters: u16) -> Result<u16, Error<E>> {
+    if meters > MAX_SENSOR_ALTITUDE {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(meters)
+}
+
+// The sensor takes the pressure in units of 100 Pa.
+fn ambient_pressure_ticks(pascals: u32) -> u32 {
+    pascals.saturating_add(50) / 100
+}
+
+fn encode_ambient_pressure<E>(pascals: u32) -> Result<u16, Error<E>> {
+    if !AMBIENT_PRESSURE_RANGE.contains(&pascals) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(ambient_pressure_ticks(pascals) as u16)
+}
+
+fn decode_ambient_pressure(ticks: u16) -> u32 {
+    ticks as u32 * 100
+}
+
+fn ambient_pressure_changed(last: Option<u32>, pascals: u32, threshold_pa: u32) -> bool {
+    last.is_none_or(|last| last.abs_diff(pascals) >= threshold_pa)
+}
+
+/// Forced recalibration is only valid right after periodic measurement was stopped.
+fn check_stopped_from_periodic<E>(stopped_from_periodic: bool) -> Result<(), Error<E>> {
+    if stopped_from_periodic {
+        Ok(())
+    } else {
+        Err(Error::InvalidState)
+    }
+}
+
+fn decode_forced_recalibration<E>(response: u16) -> Result<i16, Error<E>> {
+    // 0xffff is returned if the recalibration failed.
+    if response == 0xffff {
+        return Err(Error::RecalibrationFailed);
+    }
+
+    Ok((response as i32 - 0x8000) as i16)
+}
+
+fn decode_asc_enabled<E>(status: u16) -> Result<bool, Error<E>> {
+    match status {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(Error::InvalidResponse),
+    }
+}
+
+fn check_asc_period<E>(hours: u16) -> Result<u16, Error<E>> {
+    if !hours.is_multiple_of(ASC_PERIOD_STEP_HOURS) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(hours)
+}
+
+fn check_asc_config<E>(config: &AscConfig) -> Result<(), Error<E>> {
+    if let Some(hours) = config.initial_period_hours {
+        check_asc_period(hours)?;
+    }
+    if let Some(hours) = config.standard_period_hours {
+        check_asc_period(hours)?;
+    }
+
+    Ok(())
+}
+
+fn check_asc_periods_supported<E>(variant: Variant) -> Result<(), Error<E>> {
+    if variant.supports_asc_periods() {
+        Ok(())
+    } else {
+        Err(Error::Unsupported)
+    }
+}
+
+fn check_single_shot_supported<E>(variant: Variant) -> Result<(), Error<E>> {
+    if variant.supports_single_shot() {
+        Ok(())
+    } else {
+        Err(Error::Unsupported)
+    }
+}
+
+/// SCD4x driver. The `M` parameter selects between the runtime-checked
+/// [`Dynamic`] mode and the typestate modes, see [`mode`].
 #[derive(Debug)]
-pub struct SCD4x<I2C> {
-    sensor: Sensor<I2C>,
+pub struct SCD4x<I2C, D, M = Dynamic> {
+    sensor: Sensor<I2C, D>,
+    state: State,
+    // Whether periodic measurement was stopped since the last reinit, factory
+    // reset or power-down, as required for forced recalibration.
+    stopped_from_periodic: bool,
+    ambient_pressure: Option<u32>,
+    variant: Option<Variant>,
+    mode: PhantomData<M>,
 }
 
-impl<I2C: I2c> SCD4x<I2C> {
-    pub fn new(i2c: I2C) -> Self {
+/// Result of a typestate transition. On failure the driver is returned in its
+/// original mode together with the error.
+pub type Transition<I2C, D, From, To> = Result<
+    SCD4x<I2C, D, To>,
+    (
+        SCD4x<I2C, D, From>,
+        Error<<I2C as embedded_hal::i2c::ErrorType>::Error>,
+    ),
+>;
+
+impl<I2C: I2c, D: DelayNs> SCD4x<I2C, D> {
+    /// Creates the driver. The sensor is assumed to be idle, as it is after power-up.
+    /// `delay` is used to wait for the command execution times.
+    pub fn new(i2c: I2C, delay: D) -> Self {
         Self {
-            sensor: Sensor::new(i2c, ADDR),
+            sensor: Sensor::new(i2c, ADDR, delay),
+            state: State::Idle,
+            stopped_from_periodic: false,
+            ambient_pressure: None,
+            variant: None,
+            mode: PhantomData,
         }
     }
 
+    /// Switches to the [`Idle`] typestate mode. Fails, returning the driver
+    /// unchanged, if the sensor is not idle.
+    pub fn into_idle(self) -> Result<SCD4x<I2C, D, Idle>, Self> {
+        if self.state == State::Idle {
+            Ok(self.into_mode())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Command returns a sensor running in periodic measurement mode or low power
+    /// periodic measurement mode back to the idle state, e.g. to then allow
+    /// changing the sensor configuration or to save power.
+    /// Waits 500 ms for the sensor to become responsive before returning.
+    pub fn stop_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.leave_periodic_measurement()
+    }
+
+    /// Starts the periodic measurement mode. The signal update interval is 5 seconds.
+    pub fn start_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.enter_periodic_measurement()
+    }
+
+    /// Starts the low power periodic measurement mode. The signal update
+    /// interval is approximately 30 seconds.
+    pub fn start_low_power_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.enter_low_power_periodic_measurement()
+    }
+
+    /// Puts the sensor from idle to sleep to reduce current consumption.
+    /// Use wake_up to bring it back to idle.
+    pub fn power_down(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.enter_power_down()
+    }
+
+    /// Wakes up the sensor from sleep mode into idle mode.
+    /// The sensor does not acknowledge the wake_up command, so a NACK is not
+    /// treated as an error.
+    pub fn wake_up(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.leave_power_down()
+    }
+
+    /// Wakes up the sensor and verifies that it is responsive by reading out
+    /// the serial number.
+    pub fn wake_up_and_verify(&mut self) -> Result<u64, Error<I2C::Error>> {
+        self.wake_up()?;
+        self.get_serial_number()
+    }
+}
+
+impl<I2C: I2c, M: Mode, D: DelayNs> SCD4x<I2C, D, M> {
+    fn ensure_idle(&self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_idle()
+    }
+
+    fn ensure_awake(&self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_awake()
+    }
+
+    fn into_mode<N: Mode>(self) -> SCD4x<I2C, D, N> {
+        SCD4x {
+            sensor: self.sensor,
+            state: self.state,
+            stopped_from_periodic: self.stopped_from_periodic,
+            ambient_pressure: self.ambient_pressure,
+            variant: self.variant,
+            mode: PhantomData,
+        }
+    }
+
+    /// Returns the sensor variant cached by get_sensor_variant, if known.
+    pub fn variant(&self) -> Option<Variant> {
+        self.variant
+    }
+
+    /// Sets the sensor variant without querying the sensor, for when the part
+    /// is known at build time.
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    fn transition<N: Mode>(
+        mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), Error<I2C::Error>>,
+    ) -> Transition<I2C, D, M, N> {
+        match f(&mut self) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(err) => Err((self, err)),
+        }
+    }
+
+    /// Switches to the runtime-checked [`Dynamic`] mode.
+    pub fn into_dynamic(self) -> SCD4x<I2C, D, Dynamic> {
+        self.into_mode()
+    }
+
+    fn enter_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .send_command(&commands::START_PERIODIC_MEASUREMENTS)?;
+        self.state = State::Periodic;
+        Ok(())
+    }
+
+    fn enter_low_power_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .send_command(&commands::START_LOW_POWER_PERIODIC_MEASUREMENT)?;
+        self.state = State::LowPowerPeriodic;
+        Ok(())
+    }
+
+    fn leave_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .send_command(&commands::STOP_PERIODIC_MEASUREMENTS)?;
+        self.state = State::Idle;
+        self.stopped_from_periodic = true;
+        Ok(())
+    }
+
+    fn enter_power_down(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.sensor.send_command(&commands::POWER_DOWN)?;
+        self.state = State::PoweredDown;
+        self.stopped_from_periodic = false;
+        Ok(())
+    }
+
+    fn leave_power_down(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.state.ensure_can_wake_up()?;
+
+        // Waits even if the command was not acknowledged.
+        ignore_nack(self.sensor.start_command(&commands::WAKE_UP))?;
+        self.sensor.delay_ms(commands::WAKE_UP.exec_time_ms);
+        self.state = State::Idle;
+        Ok(())
+    }
+}
+
+impl<I2C: I2c, M: Awake, D: DelayNs> SCD4x<I2C, D, M> {
     /// Polls the sensor for whether data from a periodic or single shot measurement is ready to be read out.
     /// Returns true if successful, false if failed.
     pub fn get_data_ready_status(&mut self) -> Result<bool, Error<I2C::Error>> {
+        self.ensure_awake()?;
         let status = self
             .sensor
             .one_word_command(&commands::GET_DATA_READY_STATUS)?;
 
-        // From the datasheet, if the 11 LSB are 0, data is not ready.
-        Ok((status & 0x7FF) != 0)
+        Ok(decode_data_ready_status(status))
     }
 
+    /// Reads the sensor output. The measurement data can only be read out once
+    /// per signal update interval as the buffer is emptied upon read-out.
+    /// If no data is available in the buffer, the sensor returns a NACK.
+    /// To avoid a NACK response, the get_data_ready_status can be issued to
+    /// check data status.
+    pub fn read_measurement(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.ensure_awake()?;
+        let response = self
+            .sensor
+            .three_words_command(&commands::READ_MEASUREMENT)?;
+        Ok(Measurement::from_words(&response))
+    }
+
+    /// Reads the sensor output like read_measurement, but returns the raw words
+    /// without converting them.
+    pub fn read_measurement_raw(&mut self) -> Result<RawMeasurement, Error<I2C::Error>> {
+        self.ensure_awake()?;
+        let response = self
+            .sensor
+            .three_words_command(&commands::READ_MEASUREMENT)?;
+        Ok(RawMeasurement::from_words(&response))
+    }
+
+    /// Reads the sensor output like read_measurement, but converts it with
+    /// integer math only.
+    pub fn read_measurement_fixed(&mut self) -> Result<FixedMeasurement, Error<I2C::Error>> {
+        self.ensure_awake()?;
+        let response = self
+            .sensor
+            .three_words_command(&commands::READ_MEASUREMENT)?;
+        Ok(FixedMeasurement::from_words(&response))
+    }
+
+    /// Polls the data ready status every 100 ms until a measurement is available
+    /// and reads it out. Fails with `Error::Timeout` if no measurement becomes
+    /// available within approximately `timeout_ms`.
+    pub fn wait_for_measurement(
+        &mut self,
+        timeout_ms: u32,
+    ) -> Result<Measurement, Error<I2C::Error>> {
+        self.ensure_awake()?;
+        let mut waited_ms = 0;
+        while !self.get_data_ready_status()? {
+            if waited_ms >= timeout_ms {
+                return Err(Error::Timeout);
+            }
+            let interval_ms = DATA_READY_POLL_INTERVAL_MS.min(timeout_ms - waited_ms);
+            self.sensor.delay_ms(interval_ms);
+            waited_ms += interval_ms;
+        }
+
+        self.read_measurement()
+    }
+
+    /// Sets the ambient pressure in Pa, used to compensate the CO2 output.
+    /// Overrides any altitude compensation. Unlike most configuration commands,
+    /// this one can be issued during periodic measurement.
+    pub fn set_ambient_pressure(&mut self, pascals: u32) -> Result<(), Error<I2C::Error>> {
+        self.ensure_awake()?;
+        let ticks = encode_ambient_pressure(pascals)?;
+        self.sensor
+            .write_command_with_args(&commands::SET_AMBIENT_PRESSURE, &[ticks])?;
+        self.ambient_pressure = Some(pascals);
+        Ok(())
+    }
+
+    /// Reads out the ambient pressure in Pa used for compensation.
+    pub fn get_ambient_pressure(&mut self) -> Result<u32, Error<I2C::Error>> {
+        self.ensure_awake()?;
+        let ticks = self
+            .sensor
+            .one_word_command(&commands::GET_AMBIENT_PRESSURE)?;
+
+        Ok(decode_ambient_pressure(ticks))
+    }
+
+    /// Reads the ambient pressure in Pa from `source` and sends it to the sensor
+    /// if it differs from the last value sent by at least `threshold_pa`.
+    /// Intended to be called every measurement cycle with a barometer reading.
+    /// A `None` from the source skips the update.
+    /// Returns true if the sensor was updated.
+    pub fn update_ambient_pressure<F>(
+        &mut self,
+        source: F,
+        threshold_pa: u32,
+    ) -> Result<bool, Error<I2C::Error>>
+    where
+        F: FnOnce() -> Option<u32>,
+    {
+        self.ensure_awake()?;
+        let Some(pascals) = source() else {
+            return Ok(false);
+        };
+
+        if !ambient_pressure_changed(self.ambient_pressure, pascals, threshold_pa) {
+            return Ok(false);
+        }
+
+        self.set_ambient_pressure(pascals)?;
+        Ok(true)
+    }
+}
+
+impl<I2C: I2c, M: Configurable, D: DelayNs> SCD4x<I2C, D, M> {
     /// Reading out the serial number can be used to identify the chip and to verify the presence of the sensor.
     pub fn get_serial_number(&mut self) -> Result<u64, Error<I2C::Error>> {
+        self.ensure_idle()?;
         let words = self
             .sensor
             .three_words_command(&commands::GET_SERIAL_NUMBER)?;
 
-        Ok((words[0] as u64) << 32 | (words[1] as u64) << 16 | (words[2] as u64))
+        Ok(serial_number(&words))
     }
 
     /// The perform_self_test command can be used as an end-of-line test to check the sensor functionality.
     pub fn start_self_test(&mut self) -> Result<(), Error<I2C::Error>> {
-        self.sensor.send_command(&commands::PERFORM_SELF_TEST)?;
+        self.ensure_idle()?;
+        self.sensor.start_command(&commands::PERFORM_SELF_TEST)?;
         Ok(())
     }
 
     /// Returns true if no malfunction detected, false if failed.
     /// Result is available 10s after self-test is started.
     pub fn read_self_test_result(&mut self) -> Result<bool, Error<I2C::Error>> {
+        self.ensure_idle()?;
         let status = self.sensor.read_response_word()?;
 
         Ok(status == 0)
     }
 
-    /// Reads out the SCD4x sensor variant
+    /// Runs the self-test and waits 10 s for its result.
+    /// Unlike read_self_test_result, the report keeps the raw malfunction word.
+    pub fn perform_self_test(&mut self) -> Result<SelfTestReport, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.sensor.send_command(&commands::PERFORM_SELF_TEST)?;
+        let malfunction = self.sensor.read_response_word()?;
+
+        Ok(SelfTestReport { malfunction })
+    }
+
+    /// Reads out the SCD4x sensor variant and caches it for capability checks.
     pub fn get_sensor_variant(&mut self) -> Result<Variant, Error<I2C::Error>> {
+        self.ensure_idle()?;
         let status = self
             .sensor
             .one_word_command(&commands::GET_SENSOR_VARIANT)?;
 
-        match status >> 12 {
-            0b0000 => Ok(Variant::SCD40),
-            0b0001 => Ok(Variant::SCD41),
-            0b0101 => Ok(Variant::SCD43),
-            _ => Err(Error::InvalidResponse),
+        let variant = decode_sensor_variant(status)?;
+        self.variant = Some(variant);
+        Ok(variant)
+    }
+
+    /// Returns the cached sensor variant, reading it out on first use.
+    fn sensor_variant(&mut self) -> Result<Variant, Error<I2C::Error>> {
+        match self.variant {
+            Some(variant) => Ok(variant),
+            None => self.get_sensor_variant(),
         }
     }
 
-    /// Command returns a sensor running in periodic measurement mode or low power
-    /// periodic measurement mode back to the idle state, e.g. to then allow
-    /// changing the sensor configuration or to save power.
-    /// Note that the sensor will only respond to other commands 500 ms after the
-    /// stop_periodic_measurement command has been issued.
-    pub fn stop_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+    /// Sets the temperature offset in °C. The offset does not affect the
+    /// accuracy of the CO2 output, it only compensates for self-heating of
+    /// the sensor and the surrounding device.
+    /// To save the setting to the EEPROM, the persist_settings command must be issued.
+    pub fn set_temperature_offset(&mut self, celsius: f32) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let ticks = encode_temperature_offset(celsius)?;
         self.sensor
-            .send_command(&commands::STOP_PERIODIC_MEASUREMENTS)?;
+            .write_command_with_args(&commands::SET_TEMPERATURE_OFFSET, &[ticks])?;
         Ok(())
     }
 
-    /// Starts the periodic measurement mode. The signal update interval is 5 seconds.
-    pub fn start_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+    /// Reads out the current temperature offset in °C.
+    pub fn get_temperature_offset(&mut self) -> Result<f32, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let ticks = self
+            .sensor
+            .one_word_command(&commands::GET_TEMPERATURE_OFFSET)?;
+
+        Ok(decode_temperature_offset(ticks))
+    }
+
+    /// Sets the sensor altitude in meters above sea level, used to compensate
+    /// the CO2 output for the ambient pressure at that altitude.
+    /// Can only be set while the sensor is idle.
+    pub fn set_sensor_altitude(&mut self, meters: u16) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let meters = check_sensor_altitude(meters)?;
+
         self.sensor
-            .send_command(&commands::START_PERIODIC_MEASUREMENTS)?;
+            .write_command_with_args(&commands::SET_SENSOR_ALTITUDE, &[meters])?;
         Ok(())
     }
 
-    /// Starts the low power periodic measurement mode. The signal update
-    /// interval is approximately 30 seconds.
-    pub fn start_low_power_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
-        self.sensor
-            .send_command(&commands::START_LOW_POWER_PERIODIC_MEASUREMENT)?;
+    /// Reads out the currently configured sensor altitude in meters above sea level.
+    pub fn get_sensor_altitude(&mut self) -> Result<u16, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let meters = self
+            .sensor
+            .one_word_command(&commands::GET_SENSOR_ALTITUDE)?;
+
+        Ok(meters)
+    }
+
+    /// Performs forced recalibration (FRC) to the given CO2 reference value.
+    /// The sensor must have been operated at the reference concentration for at
+    /// least 3 minutes in periodic measurement mode and then stopped.
+    /// Fails with `Error::InvalidState` unless periodic measurement was stopped
+    /// since the last reinit, factory reset or power-down. The driver cannot
+    /// check how long the sensor was measuring.
+    /// Returns the FRC correction in ppm.
+    pub fn perform_forced_recalibration(
+        &mut self,
+        target_ppm: u16,
+    ) -> Result<i16, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        check_stopped_from_periodic(self.stopped_from_periodic)?;
+
+        let [response] = self
+            .sensor
+            .command_with_args_read(&commands::PERFORM_FORCED_RECALIBRATION, &[target_ppm])?;
+
+        decode_forced_recalibration(response)
+    }
+
+    fn ensure_asc_periods_supported(&mut self) -> Result<(), Error<I2C::Error>> {
+        let variant = self.sensor_variant()?;
+        check_asc_periods_supported(variant)
+    }
+
+    /// Enables or disables automatic self-calibration.
+    pub fn set_automatic_self_calibration_enabled(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.sensor.write_command_with_args(
+            &commands::SET_AUTOMATIC_SELF_CALIBRATION_ENABLED,
+            &[enabled as u16],
+        )?;
         Ok(())
     }
 
-    /// Reads the sensor output. The measurement data can only be read out once
-    /// per signal update interval as the buffer is emptied upon read-out.
-    /// If no data is available in the buffer, the sensor returns a NACK.
-    /// To avoid a NACK response, the get_data_ready_status can be issued to
-    /// check data status.
-    pub fn read_measurement(&mut self) -> Result<Measurement, Error<I2C::Error>> {
-        let response = self
+    /// Returns true if automatic self-calibration is enabled.
+    pub fn get_automatic_self_calibration_enabled(&mut self) -> Result<bool, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let status = self
             .sensor
-            .three_words_command(&commands::READ_MEASUREMENT)?;
-        Ok(Measurement {
-            co2_ppm: response[0],
-            temp_celsius: -45.0 + 175.0 * (response[1] as f32 / 65535.0),
-            humidity_percent: 100.0 * response[2] as f32 / 65535.0,
-        })
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_ENABLED)?;
+
+        decode_asc_enabled(status)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::SCD4x;
-    use embedded_hal::i2c::{Error, Operation};
+    /// Sets the CO2 value in ppm that automatic self-calibration uses as the baseline.
+    pub fn set_automatic_self_calibration_target(
+        &mut self,
+        target_ppm: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.sensor.write_command_with_args(
+            &commands::SET_AUTOMATIC_SELF_CALIBRATION_TARGET,
+            &[target_ppm],
+        )?;
+        Ok(())
+    }
+
+    /// Reads out the automatic self-calibration target in ppm.
+    pub fn get_automatic_self_calibration_target(&mut self) -> Result<u16, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let target_ppm = self
+            .sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_TARGET)?;
+
+        Ok(target_ppm)
+    }
+
+    /// Sets the duration of the initial automatic self-calibration period in hours.
+    /// Must be a multiple of 4 hours. Not supported by SCD40.
+    pub fn set_automatic_self_calibration_initial_period(
+        &mut self,
+        hours: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let hours = check_asc_period(hours)?;
+        self.ensure_asc_periods_supported()?;
+
+        self.sensor.write_command_with_args(
+            &commands::SET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD,
+            &[hours],
+        )?;
+        Ok(())
+    }
+
+    /// Reads out the duration of the initial automatic self-calibration period in hours.
+    /// Not supported by SCD40.
+    pub fn get_automatic_self_calibration_initial_period(
+        &mut self,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.ensure_asc_periods_supported()?;
+
+        let hours = self
+            .sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_INITIAL_PERIOD)?;
+
+        Ok(hours)
+    }
+
+    /// Sets the duration of the standard automatic self-calibration period in hours.
+    /// Must be a multiple of 4 hours. Not supported by SCD40.
+    pub fn set_automatic_self_calibration_standard_period(
+        &mut self,
+        hours: u16,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        let hours = check_asc_period(hours)?;
+        self.ensure_asc_periods_supported()?;
+
+        self.sensor.write_command_with_args(
+            &commands::SET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD,
+            &[hours],
+        )?;
+        Ok(())
+    }
+
+    /// Reads out the duration of the standard automatic self-calibration period in hours.
+    /// Not supported by SCD40.
+    pub fn get_automatic_self_calibration_standard_period(
+        &mut self,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.ensure_asc_periods_supported()?;
+
+        let hours = self
+            .sensor
+            .one_word_command(&commands::GET_AUTOMATIC_SELF_CALIBRATION_STANDARD_PERIOD)?;
+
+        Ok(hours)
+    }
+
+    /// Reads out the complete automatic self-calibration configuration.
+    /// Periods are only read on variants that support them.
+    pub fn get_asc_config(&mut self) -> Result<AscConfig, Error<I2C::Error>> {
+        let variant = self.sensor_variant()?;
+        let words = self.read_config_words(ConfigWords::layout(variant, false).asc_only())?;
+
+        words.decode_asc()
+    }
+
+    /// Writes the complete automatic self-calibration configuration.
+    /// Periods set to `None` are left unchanged. Nothing is written if `config`
+    /// is invalid or sets periods on a variant that does not support them.
+    pub fn set_asc_config(&mut self, config: &AscConfig) -> Result<(), Error<I2C::Error>> {
+        check_asc_config(config)?;
+        self.ensure_idle()?;
+        if config.initial_period_hours.is_some() || config.standard_period_hours.is_some() {
+            self.ensure_asc_periods_supported()?;
+        }
+
+        self.set_automatic_self_calibration_enabled(config.enabled)?;
+        self.set_automatic_self_calibration_target(config.target_ppm)?;
+        if let Some(hours) = config.initial_period_hours {
+            self.set_automatic_self_calibration_initial_period(hours)?;
+        }
+        if let Some(hours) = config.standard_period_hours {
+            self.set_automatic_self_calibration_standard_period(hours)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_config_words(&mut self, layout: ConfigWords) -> Result<ConfigWords, Error<I2C::Error>> {
+        self.ensure_idle()?;
+
+        let mut words = layout;
+        for (index, cmd) in layout.getters() {
+            words.0[index] = Some(self.sensor.one_word_command(cmd)?);
+        }
+
+        Ok(words)
+    }
+
+    /// Writes the words that differ from `current`. Returns true if any word was written.
+    fn write_config_words(
+        &mut self,
+        words: &ConfigWords,
+        current: &ConfigWords,
+    ) -> Result<bool, Error<I2C::Error>> {
+        let mut changed = false;
+        for (cmd, word) in words.changes(current) {
+            self.sensor.write_command_with_args(cmd, &[word])?;
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    /// Reads out the complete sensor configuration. The ambient pressure is
+    /// only read out if it was set through this driver, otherwise the sensor
+    /// uses altitude compensation and the pressure is reported as `None`.
+    pub fn read_config(&mut self) -> Result<Scd4xConfig, Error<I2C::Error>> {
+        let variant = self.sensor_variant()?;
+        let layout = ConfigWords::layout(variant, self.ambient_pressure.is_some());
+        let words = self.read_config_words(layout)?;
+
+        words.decode()
+    }
+
+    /// Writes the settings from `config` that differ from the current sensor
+    /// configuration. Values are compared at the sensor resolution, and the
+    /// ambient pressure and ASC periods set to `None` are left unchanged.
+    /// Nothing is written if `config` is invalid or sets ASC periods on a
+    /// variant that does not support them.
+    /// Returns true if any setting was written.
+    pub fn apply_config(&mut self, config: &Scd4xConfig) -> Result<bool, Error<I2C::Error>> {
+        let words = ConfigWords::encode(config)?;
+        self.ensure_idle()?;
+        let variant = self.sensor_variant()?;
+        words.check_supported(variant)?;
+
+        let layout = ConfigWords::layout(variant, config.ambient_pressure_pa.is_some());
+        let current = self.read_config_words(layout)?;
+        let changed = self.write_config_words(&words, &current)?;
+        if config.ambient_pressure_pa.is_some() {
+            self.ambient_pressure = config.ambient_pressure_pa;
+        }
+
+        Ok(changed)
+    }
+
+    /// Stores the current configuration (temperature offset, sensor altitude and
+    /// automatic self-calibration settings) in the EEPROM.
+    ///
+    /// The EEPROM is guaranteed to endure only 2000 write cycles, so the settings
+    /// are compared against the stored ones first: the sensor is reinitialized to
+    /// load the stored settings, and if they differ from the current ones, the
+    /// current settings are restored and written to the EEPROM. The ambient
+    /// pressure is not stored in the EEPROM, it is restored after the reinit.
+    /// Returns true if the EEPROM was written.
+    pub fn persist_settings(&mut self) -> Result<bool, Error<I2C::Error>> {
+        let layout = ConfigWords::persistent(self.sensor_variant()?);
+        let current = self.read_config_words(layout)?;
+        let ambient_pressure = self.ambient_pressure;
+
+        self.reinit()?;
+        if let Some(pascals) = ambient_pressure {
+            self.set_ambient_pressure(pascals)?;
+        }
+        if self.read_config_words(layout)? == current {
+            return Ok(false);
+        }
+
+        self.write_config_words(&current, &ConfigWords::default())?;
+        self.sensor.send_command(&commands::PERSIST_SETTINGS)?;
+        Ok(true)
+    }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    enum DummyError {
-        InvalidTest,
+    /// Reinitializes the sensor by reloading user settings from the EEPROM.
+    /// The ambient pressure set with set_ambient_pressure is not stored in the
+    /// EEPROM and is lost.
+    pub fn reinit(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.sensor.send_command(&commands::REINIT)?;
+        self.ambient_pressure = None;
+        self.stopped_from_periodic = false;
+        Ok(())
     }
 
-    impl Error for DummyError {
-        fn kind(&self) -> embedded_hal::i2c::ErrorKind {
-            match &self {
-                DummyError::InvalidTest => embedded_hal::i2c::ErrorKind::Other,
-            }
-        }
+    /// Resets all configuration settings stored in the EEPROM and erases the
+    /// FRC and ASC algorithm history.
+    pub fn perform_factory_reset(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.sensor.send_command(&commands::PERFORM_FACTORY_RESET)?;
+        self.ambient_pressure = None;
+        self.stopped_from_periodic = false;
+        Ok(())
     }
 
-    struct DummyBus<'a> {
-        pub response: &'a [u8],
+    fn ensure_single_shot_supported(&mut self) -> Result<(), Error<I2C::Error>> {
+        let variant = self.sensor_variant()?;
+        check_single_shot_supported(variant)
     }
 
-    impl embedded_hal::i2c::ErrorType for DummyBus<'_> {
-        type Error = DummyError;
+    /// Performs an on-demand measurement of CO2 concentration, relative humidity
+    /// and temperature, and reads it out. Takes 5 seconds. Not supported by SCD40.
+    pub fn measure_single_shot(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.ensure_single_shot_supported()?;
+
+        self.sensor.send_command(&commands::MEASURE_SINGLE_SHOT)?;
+        self.read_measurement()
     }
 
-    impl embedded_hal::i2c::I2c for DummyBus<'_> {
-        fn transaction(
-            &mut self,
-            _address: u8,
-            operations: &mut [embedded_hal::i2c::Operation],
-        ) -> Result<(), Self::Error> {
-            match operations {
-                [Operation::Write(_), Operation::Read(response)] => {
-                    if response.len() != self.response.len() {
-                        return Err(DummyError::InvalidTest);
-                    }
+    /// Performs an on-demand measurement of relative humidity and temperature
+    /// only, and reads it out. Takes 50 ms. The CO2 output is `None`.
+    /// Not supported by SCD40.
+    pub fn measure_single_shot_rht_only(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.ensure_idle()?;
+        self.ensure_single_shot_supported()?;
 
-                    response.copy_from_slice(self.response);
+        self.sensor
+            .send_command(&commands::MEASURE_SINGLE_SHOT_RHT_ONLY)?;
+        Ok(Measurement {
+            co2_ppm: None,
+            ..self.read_measurement()?
+        })
+    }
+}
 
-                    Ok(())
-                }
-                [Operation::Read(response)] => {
-                    if response.len() != self.response.len() {
-                        return Err(DummyError::InvalidTest);
-                    }
+impl<I2C: I2c, D: DelayNs> SCD4x<I2C, D, Idle> {
+    /// Starts the periodic measurement mode. The signal update interval is 5 seconds.
+    pub fn start_periodic_measurement(self) -> Transition<I2C, D, Idle, Periodic> {
+        self.transition(Self::enter_periodic_measurement)
+    }
 
-                    response.copy_from_slice(self.response);
+    /// Starts the low power periodic measurement mode. The signal update
+    /// interval is approximately 30 seconds.
+    pub fn start_low_power_periodic_measurement(
+        self,
+    ) -> Transition<I2C, D, Idle, LowPowerPeriodic> {
+        self.transition(Self::enter_low_power_periodic_measurement)
+    }
 
-                    Ok(())
-                }
-                // Other transactions are invalid
-                _ => Err(DummyError::InvalidTest),
-            }
-        }
+    /// Puts the sensor from idle to sleep to reduce current consumption.
+    pub fn power_down(self) -> Transition<I2C, D, Idle, PoweredDown> {
+        self.transition(Self::enter_power_down)
+    }
+}
+
+impl<I2C: I2c, M: Measuring, D: DelayNs> SCD4x<I2C, D, M> {
+    /// Returns the sensor to the idle mode. Waits 500 ms for the sensor to
+    /// become responsive before returning.
+    pub fn stop_periodic_measurement(self) -> Transition<I2C, D, M, Idle> {
+        self.transition(Self::leave_periodic_measurement)
     }
+}
+
+impl<I2C: I2c, D: DelayNs> SCD4x<I2C, D, PoweredDown> {
+    /// Wakes up the sensor from sleep mode into idle mode.
+    pub fn wake_up(self) -> Transition<I2C, D, PoweredDown, Idle> {
+        self.transition(Self::leave_power_down)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SCD4x;
+    use crate::test_support::{DummyBus, MockBus, NoopDelay, RecordingDelay};
 
     #[test]
     fn test_perform_self_test_success() {
         let bus = DummyBus {
             response: &[0x00, 0x00, 0x81],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
 
         assert_eq!(sensor.read_self_test_result(), Ok(true));
     }
@@ -212,17 +1304,30 @@ mod tests {
         let bus = DummyBus {
             response: &[0x14, 0x40, 0x51],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
 
         assert_eq!(sensor.read_self_test_result(), Ok(false));
     }
 
+    #[test]
+    fn test_perform_self_test_report() {
+        let mut bus = MockBus::new(&[0x14, 0x40, 0x51]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay);
+
+        let report = sensor.perform_self_test().unwrap();
+        assert_eq!(report.malfunction, 0x1440);
+        assert!(!report.passed());
+        assert_eq!(bus.written, [0x36, 0x39]);
+        assert_eq!(delay.total_ns, 10_000_000_000);
+    }
+
     #[test]
     fn test_get_serial_number() {
         let bus = DummyBus {
             response: &[0xf8, 0x96, 0x31, 0x9f, 0x07, 0xc2, 0x3b, 0xbe, 0x89],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
 
         assert_eq!(sensor.get_serial_number(), Ok(273325796834238));
     }
@@ -232,7 +1337,7 @@ mod tests {
         let bus = DummyBus {
             response: &[0x00, 0x01, 0xb0],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
 
         assert_eq!(sensor.get_data_ready_status(), Ok(true));
     }
@@ -242,7 +1347,7 @@ mod tests {
         let bus = DummyBus {
             response: &[0x80, 0x00, 0xa2],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
 
         assert_eq!(sensor.get_data_ready_status(), Ok(false));
     }
@@ -252,7 +1357,7 @@ mod tests {
         let bus = DummyBus {
             response: &[0x04, 0x40, 0x3f],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
         assert!(matches!(
             sensor.get_sensor_variant(),
             Ok(super::Variant::SCD40)
@@ -264,7 +1369,7 @@ mod tests {
         let bus = DummyBus {
             response: &[0x14, 0x40, 0x51],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
         assert!(matches!(
             sensor.get_sensor_variant(),
             Ok(super::Variant::SCD41)
@@ -276,26 +1381,793 @@ mod tests {
         let bus = DummyBus {
             response: &[0x54, 0x41, 0xe9],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
         assert!(matches!(
             sensor.get_sensor_variant(),
             Ok(super::Variant::SCD43)
         ));
     }
 
+    #[test]
+    fn test_read_measurement_raw() {
+        let bus = DummyBus {
+            response: &[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        let raw = sensor.read_measurement_raw().unwrap();
+        assert_eq!(raw.co2_ppm, 500);
+        assert_eq!(raw.temperature_ticks, 0x6667);
+        assert_eq!(raw.humidity_ticks, 0x5eb9);
+
+        let m = raw.to_measurement();
+        assert_eq!(m.co2_ppm, Some(500));
+        assert_eq!((m.temp_celsius * 100.0).floor(), 2500.0);
+        assert_eq!(raw.to_fixed().humidity_milli_percent, 37001);
+    }
+
+    #[test]
+    fn test_read_measurement_fixed() {
+        let bus = DummyBus {
+            response: &[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        let m = sensor.read_measurement_fixed().unwrap();
+        assert_eq!(m.co2_ppm, Some(500));
+        assert_eq!(m.temp_milli_celsius, 25001);
+        assert_eq!(m.humidity_milli_percent, 37001);
+        assert_eq!(m.to_string(), "500 ppm CO2, 25.0°C, 37.0% RH");
+    }
+
+    #[test]
+    fn test_fixed_measurement_display_rounds() {
+        let m = super::FixedMeasurement {
+            co2_ppm: None,
+            temp_milli_celsius: 24960,
+            humidity_milli_percent: 99950,
+        };
+        assert_eq!(m.to_string(), "25.0°C, 100.0% RH");
+
+        let m = super::FixedMeasurement {
+            co2_ppm: None,
+            temp_milli_celsius: -5649,
+            humidity_milli_percent: 37049,
+        };
+        assert_eq!(m.to_string(), "-5.6°C, 37.0% RH");
+    }
+
+    #[test]
+    fn test_wait_for_measurement() {
+        let mut bus = MockBus::sequence(&[
+            &[0x80, 0x00, 0xa2],
+            &[0x80, 0x00, 0xa2],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
+        ]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay);
+
+        let result = sensor.wait_for_measurement(1000);
+        assert!(matches!(result, Ok(m) if m.co2_ppm == Some(500)));
+        assert_eq!(
+            bus.written,
+            [0xe4, 0xb8, 0xe4, 0xb8, 0xe4, 0xb8, 0xec, 0x05]
+        );
+        // Two poll intervals plus 1 ms execution time per command.
+        assert_eq!(delay.total_ns, 204_000_000);
+    }
+
+    #[test]
+    fn test_wait_for_measurement_timeout() {
+        let bus = DummyBus {
+            response: &[0x80, 0x00, 0xa2],
+        };
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(bus, &mut delay);
+
+        assert_eq!(sensor.wait_for_measurement(250), Err(super::Error::Timeout));
+        assert_eq!(delay.total_ns, 254_000_000);
+    }
+
     #[test]
     fn test_get_measurement() {
         let bus = DummyBus {
             response: &[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
         };
-        let mut sensor = SCD4x::new(bus);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
         let result = sensor.read_measurement();
         println!("result: {:?}", result);
         assert!(matches!(
             result,
-            Ok(m) if m.co2_ppm == 500
+            Ok(m) if m.co2_ppm == Some(500)
                 && (m.temp_celsius * 100.0).floor() == 2500.0
                 && (m.humidity_percent * 100.0).floor() == 3700.0
         ));
     }
+
+    #[test]
+    fn test_set_temperature_offset() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.set_temperature_offset(5.4), Ok(()));
+        assert_eq!(bus.written, [0x24, 0x1d, 0x07, 0xe6, 0x48]);
+    }
+
+    #[test]
+    fn test_set_temperature_offset_out_of_range() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(
+            sensor.set_temperature_offset(-1.0),
+            Err(super::Error::InvalidArgument)
+        );
+        assert_eq!(
+            sensor.set_temperature_offset(25.0),
+            Err(super::Error::InvalidArgument)
+        );
+        assert!(bus.written.is_empty());
+    }
+
+    #[test]
+    fn test_get_temperature_offset() {
+        let bus = DummyBus {
+            response: &[0x09, 0x12, 0x63],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        let offset = sensor.get_temperature_offset().unwrap();
+        assert_eq!((offset * 100.0).round(), 620.0);
+    }
+
+    #[test]
+    fn test_set_sensor_altitude() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.set_sensor_altitude(2000), Ok(()));
+        assert_eq!(
+            sensor.set_sensor_altitude(3001),
+            Err(super::Error::InvalidArgument)
+        );
+        assert_eq!(bus.written, [0x24, 0x27, 0x07, 0xd0, 0x2b]);
+    }
+
+    #[test]
+    fn test_set_sensor_altitude_while_measuring() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(
+            sensor.set_sensor_altitude(1000),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(sensor.stop_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.set_sensor_altitude(1100), Ok(()));
+        assert_eq!(
+            bus.written,
+            [0x21, 0xb1, 0x3f, 0x86, 0x24, 0x27, 0x04, 0x4c, 0x42]
+        );
+    }
+
+    #[test]
+    fn test_get_sensor_altitude() {
+        let bus = DummyBus {
+            response: &[0x07, 0xd0, 0x2b],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.get_sensor_altitude(), Ok(2000));
+    }
+
+    #[test]
+    fn test_set_ambient_pressure() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.set_ambient_pressure(98_730), Ok(()));
+        assert_eq!(
+            sensor.set_ambient_pressure(60_000),
+            Err(super::Error::InvalidArgument)
+        );
+        assert_eq!(bus.written, [0x21, 0xb1, 0xe0, 0x00, 0x03, 0xdb, 0x42]);
+    }
+
+    #[test]
+    fn test_get_ambient_pressure() {
+        let bus = DummyBus {
+            response: &[0x03, 0xdb, 0x42],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.get_ambient_pressure(), Ok(98_700));
+    }
+
+    #[test]
+    fn test_update_ambient_pressure() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.update_ambient_pressure(|| None, 200), Ok(false));
+        assert_eq!(
+            sensor.update_ambient_pressure(|| Some(98_700), 200),
+            Ok(true)
+        );
+        assert_eq!(
+            sensor.update_ambient_pressure(|| Some(98_850), 200),
+            Ok(false)
+        );
+        assert_eq!(
+            sensor.update_ambient_pressure(|| Some(98_500), 200),
+            Ok(true)
+        );
+        assert_eq!(
+            bus.written,
+            [0xe0, 0x00, 0x03, 0xdb, 0x42, 0xe0, 0x00, 0x03, 0xd9, 0x20]
+        );
+    }
+
+    #[test]
+    fn test_perform_forced_recalibration() {
+        let mut bus = MockBus::new(&[0x7f, 0xce, 0x7b]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.stop_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.perform_forced_recalibration(480), Ok(-50));
+        assert_eq!(
+            bus.written,
+            [0x21, 0xb1, 0x3f, 0x86, 0x36, 0x2f, 0x01, 0xe0, 0xb4]
+        );
+    }
+
+    #[test]
+    fn test_perform_forced_recalibration_not_stopped() {
+        let mut bus = MockBus::new(&[0x7f, 0xce, 0x7b]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        // The sensor was never measuring.
+        assert_eq!(
+            sensor.perform_forced_recalibration(480),
+            Err(super::Error::InvalidState)
+        );
+
+        // Reinit discards the measurement history.
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.stop_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.reinit(), Ok(()));
+        assert_eq!(
+            sensor.perform_forced_recalibration(480),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(bus.written, [0x21, 0xb1, 0x3f, 0x86, 0x36, 0x46]);
+    }
+
+    #[test]
+    fn test_perform_forced_recalibration_failed() {
+        let bus = DummyBus {
+            response: &[0xff, 0xff, 0xac],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.stop_periodic_measurement(), Ok(()));
+        assert_eq!(
+            sensor.perform_forced_recalibration(480),
+            Err(super::Error::RecalibrationFailed)
+        );
+    }
+
+    #[test]
+    fn test_perform_forced_recalibration_while_measuring() {
+        let bus = DummyBus { response: &[] };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(
+            sensor.perform_forced_recalibration(480),
+            Err(super::Error::InvalidState)
+        );
+    }
+
+    #[test]
+    fn test_set_automatic_self_calibration_enabled() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.set_automatic_self_calibration_enabled(false), Ok(()));
+        assert_eq!(bus.written, [0x24, 0x16, 0x00, 0x00, 0x81]);
+    }
+
+    #[test]
+    fn test_get_automatic_self_calibration_enabled() {
+        let bus = DummyBus {
+            response: &[0x00, 0x01, 0xb0],
+        };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.get_automatic_self_calibration_enabled(), Ok(true));
+    }
+
+    #[test]
+    fn test_set_automatic_self_calibration_period_not_multiple_of_4() {
+        let mut bus = MockBus::new(&[0x14, 0x40, 0x51]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(
+            sensor.set_automatic_self_calibration_initial_period(45),
+            Err(super::Error::InvalidArgument)
+        );
+        assert_eq!(
+            sensor.set_automatic_self_calibration_standard_period(158),
+            Err(super::Error::InvalidArgument)
+        );
+        assert!(bus.written.is_empty());
+    }
+
+    #[test]
+    fn test_automatic_self_calibration_period_unsupported_on_scd40() {
+        let mut bus = MockBus::new(&[0x04, 0x40, 0x3f]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(
+            sensor.set_automatic_self_calibration_initial_period(44),
+            Err(super::Error::Unsupported)
+        );
+        assert_eq!(
+            sensor.get_automatic_self_calibration_standard_period(),
+            Err(super::Error::Unsupported)
+        );
+        // Only the variant query was sent, the variant is cached afterwards.
+        assert_eq!(bus.written, [0x20, 0x2f]);
+    }
+
+    #[test]
+    fn test_persist_settings_unchanged() {
+        // Every read returns the same word, so stored and current settings match.
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.persist_settings(), Ok(false));
+        assert!(bus.written.windows(2).any(|cmd| cmd == [0x36, 0x46]));
+        assert!(!bus.written.windows(2).any(|cmd| cmd == [0x36, 0x15]));
+    }
+
+    #[test]
+    fn test_persist_settings_changed() {
+        let mut bus = MockBus::sequence(&[
+            // Current settings: offset 0x0ccc, altitude 100, ASC on, target 400.
+            &[0x0c, 0xcc, 0x0f],
+            &[0x00, 0x64, 0xfe],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0x90, 0x4c],
+            // Stored settings differ in the temperature offset.
+            &[0x00, 0x00, 0x81],
+            &[0x00, 0x64, 0xfe],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0x90, 0x4c],
+        ]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(&mut bus, &mut delay).with_variant(super::Variant::SCD40);
+
+        assert_eq!(sensor.set_ambient_pressure(101_300), Ok(()));
+        assert_eq!(sensor.persist_settings(), Ok(true));
+        assert_eq!(
+            bus.written,
+            [
+                0xe0, 0x00, 0x03, 0xf5, 0xdb, // set ambient pressure
+                0x23, 0x18, 0x23, 0x22, 0x23, 0x13, 0x23, 0x3f, // read current settings
+                0x36, 0x46, // reinit
+                0xe0, 0x00, 0x03, 0xf5, 0xdb, // restore ambient pressure
+                0x23, 0x18, 0x23, 0x22, 0x23, 0x13, 0x23, 0x3f, // read stored settings
+                0x24, 0x1d, 0x0c, 0xcc, 0x0f, // set temperature offset
+                0x24, 0x27, 0x00, 0x64, 0xfe, // set sensor altitude
+                0x24, 0x16, 0x00, 0x01, 0xb0, // set ASC enabled
+                0x24, 0x3a, 0x01, 0x90, 0x4c, // set ASC target
+                0x36, 0x15, // persist settings
+            ]
+        );
+        // 1 ms per command, 30 ms reinit and 800 ms persist_settings.
+        assert_eq!(delay.total_ns, 844_000_000);
+    }
+
+    #[test]
+    fn test_read_config() {
+        let bus = MockBus::sequence(&[
+            &[0x00, 0x00, 0x81], // SCD40
+            &[0x0c, 0xcc, 0x0f],
+            &[0x00, 0x64, 0xfe],
+            &[0x03, 0xf5, 0xdb],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0x90, 0x4c],
+        ]);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.set_ambient_pressure(101_300), Ok(()));
+        let config = sensor.read_config().unwrap();
+        assert!((config.temperature_offset_celsius - 8.75).abs() < 0.01);
+        assert_eq!(config.sensor_altitude_meters, 100);
+        assert_eq!(config.ambient_pressure_pa, Some(101300));
+        assert_eq!(
+            config.asc,
+            super::AscConfig {
+                enabled: true,
+                target_ppm: 400,
+                initial_period_hours: None,
+                standard_period_hours: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_config_altitude_compensation() {
+        let mut bus = MockBus::sequence(&[
+            &[0x00, 0x00, 0x81], // SCD40
+            &[0x0c, 0xcc, 0x0f],
+            &[0x00, 0x64, 0xfe],
+            &[0x00, 0x01, 0xb0],
+            &[0x01, 0x90, 0x4c],
+        ]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        let config = sensor.read_config().unwrap();
+        assert_eq!(config.sensor_altitude_meters, 100);
+        assert_eq!(config.ambient_pressure_pa, None);
+        // The ambient pressure is not queried.
+        assert_eq!(
+            bus.written,
+            [0x20, 0x2f, 0x23, 0x18, 0x23, 0x22, 0x23, 0x13, 0x23, 0x3f]
+        );
+    }
+
+    #[test]
+    fn test_config_eq_at_sensor_resolution() {
+        let config = super::Scd4xConfig {
+            temperature_offset_celsius: 5.4,
+            sensor_altitude_meters: 100,
+            ambient_pressure_pa: Some(101_325),
+            asc: super::AscConfig {
+                enabled: true,
+                target_ppm: 400,
+                initial_period_hours: None,
+                standard_period_hours: None,
+            },
+        };
+        let ticks = super::encode_temperature_offset::<()>(5.4).unwrap();
+        let read_back = super::Scd4xConfig {
+            temperature_offset_celsius: super::decode_temperature_offset(ticks),
+            ambient_pressure_pa: Some(101_300),
+            ..config
+        };
+
+        assert_ne!(read_back.temperature_offset_celsius, 5.4);
+        assert_eq!(read_back, config);
+        assert_ne!(
+            super::Scd4xConfig {
+                ambient_pressure_pa: None,
+                ..config
+            },
+            config
+        );
+        assert_ne!(
+            super::Scd4xConfig {
+                temperature_offset_celsius: 5.5,
+                ..config
+            },
+            config
+        );
+    }
+
+    #[test]
+    fn test_apply_config_writes_differences() {
+        // Every read returns zero: offset 0, altitude 0, pressure 0, ASC off, SCD40.
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        let config = super::Scd4xConfig {
+            temperature_offset_celsius: 0.0,
+            sensor_altitude_meters: 0,
+            ambient_pressure_pa: Some(101300),
+            asc: super::AscConfig {
+                enabled: false,
+                target_ppm: 400,
+                initial_period_hours: None,
+                standard_period_hours: None,
+            },
+        };
+        assert_eq!(sensor.apply_config(&config), Ok(true));
+        assert_eq!(
+            bus.written,
+            [
+                0x20, 0x2f, // get sensor variant
+                0x23, 0x18, // get temperature offset
+                0x23, 0x22, // get sensor altitude
+                0xe0, 0x00, // get ambient pressure
+                0x23, 0x13, // get ASC enabled
+                0x23, 0x3f, // get ASC target
+                0xe0, 0x00, 0x03, 0xf5, 0xdb, // set ambient pressure
+                0x24, 0x3a, 0x01, 0x90, 0x4c, // set ASC target
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_config_invalid() {
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        let config = super::Scd4xConfig {
+            temperature_offset_celsius: 0.0,
+            sensor_altitude_meters: 3001,
+            ambient_pressure_pa: Some(101300),
+            asc: super::AscConfig {
+                enabled: true,
+                target_ppm: 400,
+                initial_period_hours: None,
+                standard_period_hours: None,
+            },
+        };
+        assert_eq!(
+            sensor.apply_config(&config),
+            Err(super::Error::InvalidArgument)
+        );
+        assert!(bus.written.is_empty());
+    }
+
+    #[test]
+    fn test_set_asc_config_unsupported() {
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]); // SCD40
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        let config = super::AscConfig {
+            enabled: true,
+            target_ppm: 400,
+            initial_period_hours: Some(44),
+            standard_period_hours: None,
+        };
+        assert_eq!(
+            sensor.set_asc_config(&config),
+            Err(super::Error::Unsupported)
+        );
+        // Nothing is written after the variant query.
+        assert_eq!(bus.written, [0x20, 0x2f]);
+    }
+
+    #[test]
+    fn test_apply_config_unsupported() {
+        let mut bus = MockBus::new(&[0x00, 0x00, 0x81]); // SCD40
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        let config = super::Scd4xConfig {
+            temperature_offset_celsius: 4.0,
+            sensor_altitude_meters: 0,
+            ambient_pressure_pa: None,
+            asc: super::AscConfig {
+                enabled: true,
+                target_ppm: 400,
+                initial_period_hours: Some(44),
+                standard_period_hours: None,
+            },
+        };
+        assert_eq!(sensor.apply_config(&config), Err(super::Error::Unsupported));
+        // Nothing is written after the variant query.
+        assert_eq!(bus.written, [0x20, 0x2f]);
+    }
+
+    #[test]
+    fn test_reinit() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.reinit(), Ok(()));
+        assert_eq!(bus.written, [0x36, 0x46]);
+    }
+
+    #[test]
+    fn test_perform_factory_reset() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(
+            sensor.perform_factory_reset(),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(sensor.stop_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.perform_factory_reset(), Ok(()));
+        assert_eq!(bus.written, [0x21, 0xb1, 0x3f, 0x86, 0x36, 0x32]);
+    }
+
+    #[test]
+    fn test_measure_single_shot() {
+        let mut bus = MockBus::sequence(&[
+            &[0x14, 0x40, 0x51],
+            &[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
+        ]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        let result = sensor.measure_single_shot();
+        assert!(matches!(result, Ok(m) if m.co2_ppm == Some(500)));
+        assert_eq!(bus.written, [0x20, 0x2f, 0x21, 0x9d, 0xec, 0x05]);
+    }
+
+    #[test]
+    fn test_measure_single_shot_rht_only() {
+        let bus = MockBus::sequence(&[
+            &[0x54, 0x41, 0xe9],
+            &[0x00, 0x00, 0x81, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c],
+        ]);
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        let result = sensor.measure_single_shot_rht_only();
+        assert!(matches!(
+            result,
+            Ok(m) if m.co2_ppm.is_none()
+                && (m.temp_celsius * 100.0).floor() == 2500.0
+        ));
+    }
+
+    #[test]
+    fn test_measure_single_shot_unsupported_on_scd40() {
+        let mut bus = MockBus::new(&[0x04, 0x40, 0x3f]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.measure_single_shot(), Err(super::Error::Unsupported));
+        assert_eq!(
+            sensor.measure_single_shot_rht_only(),
+            Err(super::Error::Unsupported)
+        );
+        assert_eq!(sensor.variant(), Some(super::Variant::SCD40));
+        assert_eq!(bus.written, [0x20, 0x2f]);
+    }
+
+    #[test]
+    fn test_known_variant_skips_query() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay).with_variant(super::Variant::SCD40);
+
+        assert_eq!(sensor.measure_single_shot(), Err(super::Error::Unsupported));
+        assert!(bus.written.is_empty());
+    }
+
+    #[test]
+    fn test_variant_capabilities() {
+        use super::Variant;
+
+        assert!(!Variant::SCD40.supports_single_shot());
+        assert!(!Variant::SCD40.supports_asc_periods());
+        assert!(Variant::SCD41.supports_single_shot());
+        assert!(Variant::SCD43.supports_asc_periods());
+        assert_eq!(Variant::SCD40.co2_accuracy().tolerance_ppm(1000), 100);
+        assert_eq!(Variant::SCD41.co2_accuracy().tolerance_ppm(1000), 90);
+        assert_eq!(Variant::SCD41.co2_accuracy().max_ppm, 5000);
+        assert_eq!(Variant::SCD43.co2_accuracy().tolerance_ppm(1000), 60);
+    }
+
+    #[test]
+    fn test_power_down() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.power_down(), Ok(()));
+        assert_eq!(
+            sensor.set_sensor_altitude(100),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(sensor.wake_up(), Ok(()));
+        assert_eq!(sensor.set_sensor_altitude(100), Ok(()));
+        assert_eq!(
+            bus.written,
+            [0x36, 0xe0, 0x36, 0xf6, 0x24, 0x27, 0x00, 0x64, 0xfe]
+        );
+    }
+
+    #[test]
+    fn test_powered_down_rejects_commands() {
+        let mut bus = MockBus::new(&[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c]);
+        let mut sensor = SCD4x::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.power_down(), Ok(()));
+        assert_eq!(
+            sensor.get_data_ready_status(),
+            Err(super::Error::InvalidState)
+        );
+        assert!(matches!(
+            sensor.read_measurement(),
+            Err(super::Error::InvalidState)
+        ));
+        assert!(matches!(
+            sensor.wait_for_measurement(1000),
+            Err(super::Error::InvalidState)
+        ));
+        assert_eq!(
+            sensor.set_ambient_pressure(101_300),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(
+            sensor.get_ambient_pressure(),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(
+            sensor.update_ambient_pressure(|| Some(101_300), 0),
+            Err(super::Error::InvalidState)
+        );
+        assert_eq!(
+            sensor.read_self_test_result(),
+            Err(super::Error::InvalidState)
+        );
+        // Only the power_down command was sent.
+        assert_eq!(bus.written, [0x36, 0xe0]);
+    }
+
+    #[test]
+    fn test_wake_up_ignores_nack() {
+        let mut bus = MockBus::new(&[0xf8, 0x96, 0x31, 0x9f, 0x07, 0xc2, 0x3b, 0xbe, 0x89]);
+        bus.nack_next_write = true;
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.wake_up_and_verify(), Ok(273325796834238));
+    }
+
+    #[test]
+    fn test_typestate_transitions() {
+        let mut bus = MockBus::new(&[0x01, 0xf4, 0x33, 0x66, 0x67, 0xa2, 0x5e, 0xb9, 0x3c]);
+        let sensor = SCD4x::new(&mut bus, NoopDelay).into_idle().unwrap();
+
+        let mut sensor = sensor.start_periodic_measurement().unwrap();
+        assert!(matches!(sensor.read_measurement(), Ok(m) if m.co2_ppm == Some(500)));
+
+        let mut sensor = sensor.stop_periodic_measurement().unwrap();
+        assert_eq!(sensor.set_sensor_altitude(100), Ok(()));
+
+        let sensor = sensor.power_down().unwrap();
+        assert!(sensor.wake_up().is_ok());
+
+        assert_eq!(
+            bus.written,
+            [
+                0x21, 0xb1, 0xec, 0x05, 0x3f, 0x86, 0x24, 0x27, 0x00, 0x64, 0xfe, 0x36, 0xe0, 0x36,
+                0xf6
+            ]
+        );
+    }
+
+    #[test]
+    fn test_into_idle_while_measuring() {
+        let bus = DummyBus { response: &[] };
+        let mut sensor = SCD4x::new(bus, NoopDelay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.get_serial_number(), Err(super::Error::InvalidState));
+        assert!(sensor.into_idle().is_err());
+    }
+
+    #[test]
+    fn test_waits_for_execution_time() {
+        let bus = DummyBus {
+            response: &[0x07, 0xd0, 0x2b],
+        };
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(bus, &mut delay);
+
+        assert_eq!(sensor.get_sensor_altitude(), Ok(2000));
+        assert_eq!(sensor.set_sensor_altitude(2000), Ok(()));
+        assert_eq!(delay.total_ns, 2_000_000);
+    }
+
+    #[test]
+    fn test_write_commands_wait_for_execution_time() {
+        let bus = DummyBus { response: &[] };
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SCD4x::new(bus, &mut delay);
+
+        assert_eq!(sensor.start_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.stop_periodic_measurement(), Ok(()));
+        assert_eq!(sensor.reinit(), Ok(()));
+        assert_eq!(delay.total_ns, 530_000_000);
+    }
 }
@@ -0,0 +1,55 @@
+//! Typestate modes of the [`SCD4x`](super::SCD4x) driver.
+//!
+//! In the [`Dynamic`] mode the driver tracks the sensor mode at runtime and
+//! rejects commands not allowed in the current mode with `Error::InvalidState`.
+//! The other modes encode the sensor mode in the driver type, so only the
+//! commands the sensor accepts in that mode are available.
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Sensor mode is tracked at runtime.
+#[derive(Debug)]
+pub struct Dynamic;
+
+/// Sensor is idle and accepts configuration commands.
+#[derive(Debug)]
+pub struct Idle;
+
+/// Sensor is in periodic measurement mode.
+#[derive(Debug)]
+pub struct Periodic;
+
+/// Sensor is in low power periodic measurement mode.
+#[derive(Debug)]
+pub struct LowPowerPeriodic;
+
+/// Sensor is in sleep mode and only accepts the wake_up command.
+#[derive(Debug)]
+pub struct PoweredDown;
+
+pub trait Mode: sealed::Sealed {}
+
+/// Modes in which the sensor accepts measurement readout and ambient pressure commands.
+pub trait Awake: Mode {}
+
+/// Modes in which the sensor accepts configuration commands.
+pub trait Configurable: Awake {}
+
+/// Periodic measurement modes.
+pub trait Measuring: Awake {}
+
+macro_rules! impl_mode {
+    ($mode:ty: $($marker:ident),*) => {
+        impl sealed::Sealed for $mode {}
+        impl Mode for $mode {}
+        $(impl $marker for $mode {})*
+    };
+}
+
+impl_mode!(Dynamic: Awake, Configurable);
+impl_mode!(Idle: Awake, Configurable);
+impl_mode!(Periodic: Awake, Measuring);
+impl_mode!(LowPowerPeriodic: Awake, Measuring);
+impl_mode!(PoweredDown:);
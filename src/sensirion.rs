@@ -1,7 +1,28 @@
-use embedded_hal::i2c::I2c;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{ErrorKind, I2c};
 use thiserror::Error;
 
-pub type Cmd = [u8; 2];
+#[cfg(feature = "async")]
+pub mod asynch;
+
+/// Sensor command: the 16-bit command code and the datasheet execution time,
+/// which must pass before the response can be read.
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq)]
+pub struct Cmd {
+    pub code: [u8; 2],
+    pub exec_time_ms: u32,
+}
+
+impl Cmd {
+    pub const fn new(code: [u8; 2], exec_time_ms: u32) -> Self {
+        Self { code, exec_time_ms }
+    }
+}
+
+// Longest argument list among supported Sensirion commands (SGP40
+// measure_raw_signal takes humidity and temperature).
+const MAX_ARGS: usize = 2;
+const MAX_FRAME_LEN: usize = 2 + 3 * MAX_ARGS;
 
 #[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Error)]
 pub enum Error<I2cError> {
@@ -9,6 +30,18 @@ pub enum Error<I2cError> {
     InvalidResponse,
     #[error("invalid CRC")]
     InvalidCrc,
+    #[error("invalid argument")]
+    InvalidArgument,
+    #[error("command not allowed in current sensor state")]
+    InvalidState,
+    #[error("forced recalibration failed")]
+    RecalibrationFailed,
+    #[error("command not supported by this sensor variant")]
+    Unsupported,
+    #[error("timed out waiting for the sensor")]
+    Timeout,
+    #[error("unexpected self-test result {0:#06x}")]
+    UnexpectedSelfTestResult(u16),
     #[error(transparent)]
     I2c(#[from] I2cError),
 }
@@ -25,89 +58,195 @@ where
     }
 }
 
-#[derive(Debug)]
-pub struct Sensor<I2C> {
-    i2c: I2C,
-    addr: u8,
+// https://sensirion.com/media/documents/296373BB/6203C5DF/Sensirion_Gas_Sensors_Datasheet_SGP40.pdf
+// Section 4.6
+fn crc(data: &[u8; 2]) -> u8 {
+    let mut crc = 0xff;
+
+    for byte in data {
+        crc ^= byte;
+
+        for _ in 0..8 {
+            if crc & 0x80 != 0 {
+                crc = (crc << 1) ^ 0x31;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+
+    crc
 }
 
-impl<I2C> Sensor<I2C> {
-    pub fn new(i2c: I2C, addr: u8) -> Self {
-        Self { i2c, addr }
+fn check_crc<E>(data: &[u8; 3]) -> Result<(), Error<E>> {
+    if crc(&[data[0], data[1]]) != data[2] {
+        Err(Error::InvalidCrc)
+    } else {
+        Ok(())
     }
+}
 
-    // https://sensirion.com/media/documents/296373BB/6203C5DF/Sensirion_Gas_Sensors_Datasheet_SGP40.pdf
-    // Section 4.6
-    fn crc(data: &[u8; 2]) -> u8 {
-        let mut crc = 0xff;
+/// Builds a command frame: the command followed by every argument word
+/// with its CRC byte. Returns the frame length.
+fn build_frame<E>(
+    cmd: &Cmd,
+    args: &[u16],
+    frame: &mut [u8; MAX_FRAME_LEN],
+) -> Result<usize, Error<E>> {
+    if args.len() > MAX_ARGS {
+        return Err(Error::InvalidArgument);
+    }
 
-        for byte in data {
-            crc ^= byte;
+    frame[..2].copy_from_slice(&cmd.code);
+    for (arg, chunk) in args.iter().zip(frame[2..].chunks_exact_mut(3)) {
+        let word = arg.to_be_bytes();
+        chunk[..2].copy_from_slice(&word);
+        chunk[2] = crc(&word);
+    }
 
-            for _ in 0..8 {
-                if crc & 0x80 != 0 {
-                    crc = (crc << 1) ^ 0x31;
-                } else {
-                    crc <<= 1;
-                }
-            }
-        }
+    Ok(2 + 3 * args.len())
+}
 
-        crc
+/// Checks the CRC of every received word and converts them to u16.
+fn decode_words<const N: usize, E>(data: &[[u8; 3]; N]) -> Result<[u16; N], Error<E>> {
+    for piece in data {
+        check_crc(piece)?;
     }
 
-    fn check_crc<E>(data: &[u8; 3]) -> Result<(), Error<E>> {
-        if Self::crc(&[data[0], data[1]]) != data[2] {
-            Err(Error::InvalidCrc)
-        } else {
-            Ok(())
-        }
+    Ok(data.map(|piece| u16::from_be_bytes([piece[0], piece[1]])))
+}
+
+/// Combines the three words of a Sensirion 48-bit serial number.
+pub fn serial_number(words: &[u16; 3]) -> u64 {
+    (words[0] as u64) << 32 | (words[1] as u64) << 16 | (words[2] as u64)
+}
+
+/// Converts a raw temperature signal to milli-degrees Celsius using integer
+/// math only. Approximates `-45 + 175 * ticks / 65535` by dividing by 2^16,
+/// which is off by at most 3 m°C.
+pub fn milli_celsius(ticks: u16) -> i32 {
+    ((21875 * ticks as i32) >> 13) - 45000
+}
+
+/// Converts a raw relative humidity signal to milli-percent RH using integer
+/// math only. Approximates `100 * ticks / 65535` by dividing by 2^16.
+pub fn milli_percent_rh(ticks: u16) -> u32 {
+    (12500 * ticks as u32) >> 13
+}
+
+/// Treats a NACK as success, for commands the sensor does not acknowledge.
+pub fn ignore_nack<E: embedded_hal::i2c::Error>(
+    result: Result<(), Error<E>>,
+) -> Result<(), Error<E>> {
+    match result {
+        Err(Error::I2c(err)) if matches!(err.kind(), ErrorKind::NoAcknowledge(_)) => Ok(()),
+        result => result,
     }
 }
 
-impl<I2C: I2c> Sensor<I2C> {
-    pub fn send_command(&mut self, cmd: &Cmd) -> Result<(), Error<I2C::Error>> {
-        self.i2c.write(self.addr, cmd)?;
+/// Maps a NACK to `None`, for responses the sensor is not ready to send yet.
+pub fn nack_as_none<T, E: embedded_hal::i2c::Error>(
+    result: Result<T, Error<E>>,
+) -> Result<Option<T>, Error<E>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::I2c(err)) if matches!(err.kind(), ErrorKind::NoAcknowledge(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Debug)]
+pub struct Sensor<I2C, D> {
+    i2c: I2C,
+    addr: u8,
+    delay: D,
+}
+
+impl<I2C, D> Sensor<I2C, D> {
+    /// Creates a sensor that uses `delay` to wait for the command execution times.
+    pub fn new(i2c: I2C, addr: u8, delay: D) -> Self {
+        Self { i2c, addr, delay }
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> Sensor<I2C, D> {
+    pub fn delay_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms);
+    }
+
+    fn write_frame(&mut self, cmd: &Cmd, args: &[u16]) -> Result<(), Error<I2C::Error>> {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        let len = build_frame(cmd, args, &mut frame)?;
+
+        self.i2c.write(self.addr, &frame[..len])?;
         Ok(())
     }
 
-    pub fn read_response_word(&mut self) -> Result<u16, Error<I2C::Error>> {
-        let mut result = [0u8; 3];
+    /// Sends a command without waiting for its execution time, for commands
+    /// whose response is read out later with read_response_words.
+    pub fn start_command(&mut self, cmd: &Cmd) -> Result<(), Error<I2C::Error>> {
+        self.write_frame(cmd, &[])
+    }
 
-        self.i2c.read(self.addr, &mut result)?;
-        Self::check_crc(&result)?;
+    /// Sends a command and waits for its execution time.
+    pub fn send_command(&mut self, cmd: &Cmd) -> Result<(), Error<I2C::Error>> {
+        self.write_command_with_args(cmd, &[])
+    }
 
-        Ok(u16::from_be_bytes([result[0], result[1]]))
+    /// Sends a command with argument words and waits for its execution time.
+    pub fn write_command_with_args(
+        &mut self,
+        cmd: &Cmd,
+        args: &[u16],
+    ) -> Result<(), Error<I2C::Error>> {
+        self.write_frame(cmd, args)?;
+        self.delay.delay_ms(cmd.exec_time_ms);
+        Ok(())
     }
 
-    pub fn one_word_command(&mut self, cmd: &Cmd) -> Result<u16, Error<I2C::Error>> {
-        let mut result = [0u8; 3];
+    /// Sends a command with argument words, waits for its execution time and
+    /// reads N response words.
+    pub fn command_with_args_read<const N: usize>(
+        &mut self,
+        cmd: &Cmd,
+        args: &[u16],
+    ) -> Result<[u16; N], Error<I2C::Error>> {
+        self.write_command_with_args(cmd, args)?;
+        self.read_response_words()
+    }
+
+    /// Sends a command and reads N response words, checking the CRC of each.
+    /// Waits for the command execution time in between.
+    pub fn read_words<const N: usize>(&mut self, cmd: &Cmd) -> Result<[u16; N], Error<I2C::Error>> {
+        self.command_with_args_read(cmd, &[])
+    }
 
-        self.i2c.write_read(self.addr, cmd, &mut result)?;
-        Self::check_crc(&result)?;
+    /// Reads N response words of a previously sent command.
+    pub fn read_response_words<const N: usize>(&mut self) -> Result<[u16; N], Error<I2C::Error>> {
+        let mut result = [[0u8; 3]; N];
 
-        Ok(u16::from_be_bytes([result[0], result[1]]))
+        self.i2c.read(self.addr, result.as_flattened_mut())?;
+        decode_words(&result)
     }
 
-    pub fn three_words_command(&mut self, cmd: &Cmd) -> Result<[u16; 3], Error<I2C::Error>> {
-        let mut result = [0u8; 9];
+    pub fn read_response_word(&mut self) -> Result<u16, Error<I2C::Error>> {
+        let [word] = self.read_response_words()?;
+        Ok(word)
+    }
 
-        self.i2c.write_read(self.addr, cmd, &mut result)?;
-        for piece in result.as_chunks::<3>().0 {
-            Self::check_crc(piece)?;
-        }
+    pub fn one_word_command(&mut self, cmd: &Cmd) -> Result<u16, Error<I2C::Error>> {
+        let [word] = self.read_words(cmd)?;
+        Ok(word)
+    }
 
-        Ok([
-            u16::from_be_bytes([result[0], result[1]]),
-            u16::from_be_bytes([result[3], result[4]]),
-            u16::from_be_bytes([result[6], result[7]]),
-        ])
+    pub fn three_words_command(&mut self, cmd: &Cmd) -> Result<[u16; 3], Error<I2C::Error>> {
+        self.read_words(cmd)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Sensor;
+    use super::{Cmd, build_frame, check_crc, decode_words, milli_celsius, milli_percent_rh};
     use embedded_hal::i2c::Error;
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -121,12 +260,51 @@ mod tests {
 
     #[test]
     fn test_crc() {
+        assert_eq!(check_crc::<DummyError>(&[0xbe, 0xef, 0x92]), Ok(()));
+        assert_eq!(
+            check_crc::<DummyError>(&[0xbe, 0x01, 0x92]),
+            Err(super::Error::InvalidCrc)
+        );
+    }
+
+    #[test]
+    fn test_build_frame() {
+        let mut frame = [0u8; super::MAX_FRAME_LEN];
+
+        assert_eq!(
+            build_frame::<DummyError>(&Cmd::new([0x26, 0x0f], 30), &[0x8000, 0x6666], &mut frame),
+            Ok(8)
+        );
+        assert_eq!(frame[..8], [0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93]);
+
+        assert_eq!(
+            build_frame::<DummyError>(&Cmd::new([0x36, 0x82], 1), &[], &mut frame),
+            Ok(2)
+        );
+        assert_eq!(
+            build_frame::<DummyError>(&Cmd::new([0x36, 0x82], 1), &[0; 3], &mut frame),
+            Err(super::Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_fixed_point_conversions() {
+        assert_eq!(milli_celsius(0), -45000);
+        assert_eq!(milli_celsius(0x6667), 25001);
+        assert_eq!(milli_celsius(0xffff), 129997);
+        assert_eq!(milli_percent_rh(0), 0);
+        assert_eq!(milli_percent_rh(0x5eb9), 37001);
+        assert_eq!(milli_percent_rh(0xffff), 99998);
+    }
+
+    #[test]
+    fn test_decode_words() {
         assert_eq!(
-            Sensor::<()>::check_crc::<DummyError>(&[0xbe, 0xef, 0x92]),
-            Ok(())
+            decode_words::<2, DummyError>(&[[0xbe, 0xef, 0x92], [0x00, 0x00, 0x81]]),
+            Ok([0xbeef, 0x0000])
         );
         assert_eq!(
-            Sensor::<()>::check_crc::<DummyError>(&[0xbe, 0x01, 0x92]),
+            decode_words::<2, DummyError>(&[[0xbe, 0xef, 0x92], [0x00, 0x01, 0x81]]),
             Err(super::Error::InvalidCrc)
         );
     }
@@ -0,0 +1,93 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use super::{Cmd, Error, MAX_FRAME_LEN, build_frame, decode_words};
+
+/// Async counterpart of [`super::Sensor`]. The delay is mandatory, as waiting
+/// for the command execution time does not block the executor.
+#[derive(Debug)]
+pub struct Sensor<I2C, D> {
+    i2c: I2C,
+    addr: u8,
+    delay: D,
+}
+
+impl<I2C: I2c, D: DelayNs> Sensor<I2C, D> {
+    pub fn new(i2c: I2C, addr: u8, delay: D) -> Self {
+        Self { i2c, addr, delay }
+    }
+
+    pub async fn delay_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms).await;
+    }
+
+    async fn write_frame(&mut self, cmd: &Cmd, args: &[u16]) -> Result<(), Error<I2C::Error>> {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        let len = build_frame(cmd, args, &mut frame)?;
+
+        self.i2c.write(self.addr, &frame[..len]).await?;
+        Ok(())
+    }
+
+    /// Sends a command without waiting for its execution time, for commands
+    /// whose response is read out later with read_response_words.
+    pub async fn start_command(&mut self, cmd: &Cmd) -> Result<(), Error<I2C::Error>> {
+        self.write_frame(cmd, &[]).await
+    }
+
+    /// Sends a command and waits for its execution time.
+    pub async fn send_command(&mut self, cmd: &Cmd) -> Result<(), Error<I2C::Error>> {
+        self.write_command_with_args(cmd, &[]).await
+    }
+
+    /// Sends a command with argument words and waits for its execution time.
+    pub async fn write_command_with_args(
+        &mut self,
+        cmd: &Cmd,
+        args: &[u16],
+    ) -> Result<(), Error<I2C::Error>> {
+        self.write_frame(cmd, args).await?;
+        self.delay.delay_ms(cmd.exec_time_ms).await;
+        Ok(())
+    }
+
+    /// Sends a command with argument words, waits for its execution time and
+    /// reads N response words.
+    pub async fn command_with_args_read<const N: usize>(
+        &mut self,
+        cmd: &Cmd,
+        args: &[u16],
+    ) -> Result<[u16; N], Error<I2C::Error>> {
+        self.write_command_with_args(cmd, args).await?;
+        self.read_response_words().await
+    }
+
+    /// Sends a command and reads N response words, checking the CRC of each.
+    /// Waits for the command execution time in between.
+    pub async fn read_words<const N: usize>(
+        &mut self,
+        cmd: &Cmd,
+    ) -> Result<[u16; N], Error<I2C::Error>> {
+        self.command_with_args_read(cmd, &[]).await
+    }
+
+    /// Reads N response words of a previously sent command.
+    pub async fn read_response_words<const N: usize>(
+        &mut self,
+    ) -> Result<[u16; N], Error<I2C::Error>> {
+        let mut result = [[0u8; 3]; N];
+
+        self.i2c.read(self.addr, result.as_flattened_mut()).await?;
+        decode_words(&result)
+    }
+
+    pub async fn one_word_command(&mut self, cmd: &Cmd) -> Result<u16, Error<I2C::Error>> {
+        let [word] = self.read_words(cmd).await?;
+        Ok(word)
+    }
+
+    pub async fn read_response_word(&mut self) -> Result<u16, Error<I2C::Error>> {
+        let [word] = self.read_response_words().await?;
+        Ok(word)
+    }
+}
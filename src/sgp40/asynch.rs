@@ -0,0 +1,183 @@
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use super::*;
+use crate::sensirion::asynch::Sensor;
+
+/// Async SGP40 driver. Waits for command execution times with the owned delay.
+#[derive(Debug)]
+pub struct SGP40<I2C, D> {
+    sensor: Sensor<I2C, D>,
+}
+
+impl<I2C: I2c, D: DelayNs> SGP40<I2C, D> {
+    pub fn new(i2c: I2C, delay: D) -> Self {
+        Self {
+            sensor: Sensor::new(i2c, ADDR, delay),
+        }
+    }
+
+    /// Starts the sensor self-test. The result is available after 320 ms.
+    pub async fn start_self_test(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor
+            .start_command(&commands::EXECUTE_SELF_TEST)
+            .await
+    }
+
+    /// Reads out the self-test result, or `None` if the test is still running.
+    /// Returns true if successful, false if failed.
+    pub async fn poll_self_test(&mut self) -> Result<Option<bool>, Error<I2C::Error>> {
+        match nack_as_none(self.sensor.read_response_word().await)? {
+            Some(result) => decode_self_test(result).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Performs sensor self-test and waits 320 ms for the result.
+    /// Returns true if successful, false if failed.
+    /// An unknown result pattern is returned as `Error::UnexpectedSelfTestResult`.
+    pub async fn self_test(&mut self) -> Result<bool, Error<I2C::Error>> {
+        self.start_self_test().await?;
+        self.sensor
+            .delay_ms(commands::EXECUTE_SELF_TEST.exec_time_ms)
+            .await;
+        let result = self.sensor.read_response_word().await?;
+
+        decode_self_test(result)
+    }
+
+    /// Measures the raw VOC signal, compensated for the given relative humidity
+    /// and temperature. Returns the SRAW ticks.
+    pub async fn measure_raw_signal(
+        &mut self,
+        rh_percent: f32,
+        temp_celsius: f32,
+    ) -> Result<u16, Error<I2C::Error>> {
+        let humidity = encode_humidity(rh_percent)?;
+        let temperature = encode_temperature(temp_celsius)?;
+
+        self.measure_raw_signal_ticks(humidity, temperature).await
+    }
+
+    /// Measures the raw VOC signal without humidity compensation, assuming
+    /// 50 %RH and 25 °C. Returns the SRAW ticks.
+    pub async fn measure_raw_signal_uncompensated(&mut self) -> Result<u16, Error<I2C::Error>> {
+        self.measure_raw_signal_ticks(DEFAULT_HUMIDITY_TICKS, DEFAULT_TEMPERATURE_TICKS)
+            .await
+    }
+
+    /// Measures the compensated raw signal and feeds it to `algorithm`.
+    /// Must be called once per sampling interval of the algorithm, every second
+    /// by default. Returns the VOC index.
+    pub async fn measure_voc_index(
+        &mut self,
+        algorithm: &mut VocAlgorithm,
+        rh_percent: f32,
+        temp_celsius: f32,
+    ) -> Result<u16, Error<I2C::Error>> {
+        let sraw = self.measure_raw_signal(rh_percent, temp_celsius).await?;
+
+        Ok(algorithm.process(sraw))
+    }
+
+    /// Measures the VOC index with the heater turned off between samples, for
+    /// low power operation. See [`super::SGP40::measure_voc_index_duty_cycled`].
+    pub async fn measure_voc_index_duty_cycled(
+        &mut self,
+        algorithm: &mut VocAlgorithm,
+        rh_percent: f32,
+        temp_celsius: f32,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.measure_raw_signal(rh_percent, temp_celsius).await?;
+        self.sensor.delay_ms(HEATER_WARM_UP_MS).await;
+        let sraw = self.measure_raw_signal(rh_percent, temp_celsius).await?;
+        self.turn_heater_off().await?;
+
+        let index = algorithm.process(sraw);
+        self.sensor
+            .delay_ms(duty_cycle_idle_ms(algorithm.sampling_interval()))
+            .await;
+        Ok(index)
+    }
+
+    /// Turns the hotplate off and puts the sensor into idle mode.
+    pub async fn turn_heater_off(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor.send_command(&commands::TURN_HEATER_OFF).await
+    }
+
+    async fn measure_raw_signal_ticks(
+        &mut self,
+        humidity: u16,
+        temperature: u16,
+    ) -> Result<u16, Error<I2C::Error>> {
+        let [sraw] = self
+            .sensor
+            .command_with_args_read(&commands::MEASURE_RAW_SIGNAL, &[humidity, temperature])
+            .await?;
+
+        Ok(sraw)
+    }
+
+    /// Reading out the serial number can be used to identify the chip and to verify the presence of the sensor.
+    pub async fn get_serial_number(&mut self) -> Result<u64, Error<I2C::Error>> {
+        let words = self.sensor.read_words(&commands::GET_SERIAL_NUMBER).await?;
+
+        Ok(serial_number(&words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SGP40;
+    use crate::test_support::{MockBus, RecordingDelay};
+    use crate::voc_index::VocAlgorithm;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn test_measure_raw_signal() {
+        let mut bus = MockBus::new(&[0x80, 0x00, 0xa2]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SGP40::new(&mut bus, &mut delay);
+
+        assert_eq!(block_on(sensor.measure_raw_signal(50.0, 25.0)), Ok(0x8000));
+        assert_eq!(
+            bus.written,
+            [0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93]
+        );
+        assert_eq!(delay.total_ns, 30_000_000);
+    }
+
+    #[test]
+    fn test_self_test() {
+        let mut bus = MockBus::new(&[0xd4, 0x00, 0xc6]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SGP40::new(&mut bus, &mut delay);
+
+        assert_eq!(block_on(sensor.self_test()), Ok(true));
+        assert_eq!(bus.written, [0x28, 0x0e]);
+        assert_eq!(delay.total_ns, 320_000_000);
+    }
+
+    #[test]
+    fn test_measure_voc_index_duty_cycled() {
+        let mut bus = MockBus::new(&[0x75, 0x30, 0x08]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SGP40::new(&mut bus, &mut delay);
+        let mut algorithm = VocAlgorithm::with_sampling_interval(10.0);
+
+        assert_eq!(
+            block_on(sensor.measure_voc_index_duty_cycled(&mut algorithm, 50.0, 25.0)),
+            Ok(0)
+        );
+        assert_eq!(
+            bus.written,
+            [
+                0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93, // measure, discarded
+                0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93, // measure
+                0x36, 0x15, // heater off
+            ]
+        );
+        // The whole call takes exactly one sampling interval.
+        assert_eq!(delay.total_ns, 10_000_000_000);
+    }
+}
@@ -1,33 +1,164 @@
 use crate::sensirion::*;
+use crate::voc_index::VocAlgorithm;
 
+#[cfg(feature = "async")]
+pub mod asynch;
 pub mod commands;
 
+use embedded_hal::delay::DelayNs;
 use embedded_hal::i2c::I2c;
 
 const ADDR: u8 = 0x59;
+// Compensation words for 50 %RH and 25 °C, used when no humidity sensor is available.
+const DEFAULT_HUMIDITY_TICKS: u16 = 0x8000;
+const DEFAULT_TEMPERATURE_TICKS: u16 = 0x6666;
+// Time for the hotplate to reach its operating temperature after the heater
+// was turned off, as used in Sensirion's low power sampling example.
+const HEATER_WARM_UP_MS: u32 = 170;
+
+fn encode_humidity<E>(rh_percent: f32) -> Result<u16, Error<E>> {
+    if !(0.0..=100.0).contains(&rh_percent) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok((rh_percent * 65535.0 / 100.0 + 0.5) as u16)
+}
+
+fn encode_temperature<E>(temp_celsius: f32) -> Result<u16, Error<E>> {
+    if !(-45.0..=130.0).contains(&temp_celsius) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(((temp_celsius + 45.0) * 65535.0 / 175.0 + 0.5) as u16)
+}
+
+/// Time left in a sampling interval after a duty-cycled measurement.
+fn duty_cycle_idle_ms(sampling_interval: f32) -> u32 {
+    let busy_ms = 2 * commands::MEASURE_RAW_SIGNAL.exec_time_ms
+        + HEATER_WARM_UP_MS
+        + commands::TURN_HEATER_OFF.exec_time_ms;
+    ((sampling_interval * 1000.0) as u32).saturating_sub(busy_ms)
+}
+
+fn decode_self_test<E>(result: u16) -> Result<bool, Error<E>> {
+    match result >> 8 {
+        0xd4 => Ok(true),
+        0x4b => Ok(false),
+        _ => Err(Error::UnexpectedSelfTestResult(result)),
+    }
+}
 
 #[derive(Debug)]
-pub struct SGP40<I2C> {
-    sensor: Sensor<I2C>,
+pub struct SGP40<I2C, D> {
+    sensor: Sensor<I2C, D>,
 }
 
-impl<I2C: I2c> SGP40<I2C> {
-    pub fn new(i2c: I2C) -> Self {
+impl<I2C: I2c, D: DelayNs> SGP40<I2C, D> {
+    /// Creates the driver. `delay` is used to wait for the command execution times.
+    pub fn new(i2c: I2C, delay: D) -> Self {
         Self {
-            sensor: Sensor::new(i2c, ADDR),
+            sensor: Sensor::new(i2c, ADDR, delay),
+        }
+    }
+
+    /// Starts the sensor self-test. The result is available after 320 ms.
+    pub fn start_self_test(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor.start_command(&commands::EXECUTE_SELF_TEST)
+    }
+
+    /// Reads out the self-test result, or `None` if the test is still running.
+    /// Returns true if successful, false if failed.
+    pub fn poll_self_test(&mut self) -> Result<Option<bool>, Error<I2C::Error>> {
+        match nack_as_none(self.sensor.read_response_word())? {
+            Some(result) => decode_self_test(result).map(Some),
+            None => Ok(None),
         }
     }
 
-    /// Performs sensor self-test.
+    /// Performs sensor self-test and waits 320 ms for the result.
     /// Returns true if successful, false if failed.
+    /// An unknown result pattern is returned as `Error::UnexpectedSelfTestResult`.
     pub fn self_test(&mut self) -> Result<bool, Error<I2C::Error>> {
-        let result = self.sensor.one_word_command(&commands::EXECUTE_SELF_TEST)?;
+        self.start_self_test()?;
+        self.sensor
+            .delay_ms(commands::EXECUTE_SELF_TEST.exec_time_ms);
+        let result = self.sensor.read_response_word()?;
 
-        match result >> 8 {
-            0xd4 => Ok(true),
-            0x4b => Ok(false),
-            _ => Err(Error::InvalidResponse),
-        }
+        decode_self_test(result)
+    }
+
+    /// Measures the raw VOC signal, compensated for the given relative humidity
+    /// and temperature. Returns the SRAW ticks.
+    pub fn measure_raw_signal(
+        &mut self,
+        rh_percent: f32,
+        temp_celsius: f32,
+    ) -> Result<u16, Error<I2C::Error>> {
+        let humidity = encode_humidity(rh_percent)?;
+        let temperature = encode_temperature(temp_celsius)?;
+
+        self.measure_raw_signal_ticks(humidity, temperature)
+    }
+
+    /// Measures the raw VOC signal without humidity compensation, assuming
+    /// 50 %RH and 25 °C. Returns the SRAW ticks.
+    pub fn measure_raw_signal_uncompensated(&mut self) -> Result<u16, Error<I2C::Error>> {
+        self.measure_raw_signal_ticks(DEFAULT_HUMIDITY_TICKS, DEFAULT_TEMPERATURE_TICKS)
+    }
+
+    /// Measures the compensated raw signal and feeds it to `algorithm`.
+    /// Must be called once per sampling interval of the algorithm, every second
+    /// by default. Returns the VOC index.
+    pub fn measure_voc_index(
+        &mut self,
+        algorithm: &mut VocAlgorithm,
+        rh_percent: f32,
+        temp_celsius: f32,
+    ) -> Result<u16, Error<I2C::Error>> {
+        let sraw = self.measure_raw_signal(rh_percent, temp_celsius)?;
+
+        Ok(algorithm.process(sraw))
+    }
+
+    /// Measures the VOC index with the heater turned off between samples, for
+    /// low power operation. The first measurement heats up the hotplate and is
+    /// discarded, the second one is fed to `algorithm`. Afterwards the heater is
+    /// turned off and the driver waits for the rest of the algorithm sampling
+    /// interval, so calling this in a loop keeps the algorithm cadence.
+    /// Sensirion recommends a sampling interval of 10 s for low power operation,
+    /// see [`VocAlgorithm::with_sampling_interval`].
+    pub fn measure_voc_index_duty_cycled(
+        &mut self,
+        algorithm: &mut VocAlgorithm,
+        rh_percent: f32,
+        temp_celsius: f32,
+    ) -> Result<u16, Error<I2C::Error>> {
+        self.measure_raw_signal(rh_percent, temp_celsius)?;
+        self.sensor.delay_ms(HEATER_WARM_UP_MS);
+        let sraw = self.measure_raw_signal(rh_percent, temp_celsius)?;
+        self.turn_heater_off()?;
+
+        let index = algorithm.process(sraw);
+        self.sensor
+            .delay_ms(duty_cycle_idle_ms(algorithm.sampling_interval()));
+        Ok(index)
+    }
+
+    /// Turns the hotplate off and puts the sensor into idle mode.
+    pub fn turn_heater_off(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.sensor.send_command(&commands::TURN_HEATER_OFF)
+    }
+
+    fn measure_raw_signal_ticks(
+        &mut self,
+        humidity: u16,
+        temperature: u16,
+    ) -> Result<u16, Error<I2C::Error>> {
+        let [sraw] = self
+            .sensor
+            .command_with_args_read(&commands::MEASURE_RAW_SIGNAL, &[humidity, temperature])?;
+
+        Ok(sraw)
     }
 
     /// Reading out the serial number can be used to identify the chip and to verify the presence of the sensor.
@@ -36,6 +167,159 @@ impl<I2C: I2c> SGP40<I2C> {
             .sensor
             .three_words_command(&commands::GET_SERIAL_NUMBER)?;
 
-        Ok((words[0] as u64) << 32 | (words[1] as u64) << 16 | (words[2] as u64))
+        Ok(serial_number(&words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SGP40;
+    use crate::test_support::{DummyBus, MockBus, NoopDelay, RecordingDelay};
+    use crate::voc_index::VocAlgorithm;
+
+    #[test]
+    fn test_measure_raw_signal() {
+        let mut bus = MockBus::new(&[0x80, 0x00, 0xa2]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SGP40::new(&mut bus, &mut delay);
+
+        assert_eq!(sensor.measure_raw_signal(50.0, 25.0), Ok(0x8000));
+        assert_eq!(
+            bus.written,
+            [0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93]
+        );
+        assert_eq!(delay.total_ns, 30_000_000);
+    }
+
+    #[test]
+    fn test_measure_raw_signal_uncompensated() {
+        let mut bus = MockBus::new(&[0x80, 0x00, 0xa2]);
+        let mut sensor = SGP40::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.measure_raw_signal_uncompensated(), Ok(0x8000));
+        assert_eq!(
+            bus.written,
+            [0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93]
+        );
+    }
+
+    #[test]
+    fn test_measure_raw_signal_invalid() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SGP40::new(&mut bus, NoopDelay);
+
+        assert_eq!(
+            sensor.measure_raw_signal(101.0, 25.0),
+            Err(super::Error::InvalidArgument)
+        );
+        assert_eq!(
+            sensor.measure_raw_signal(50.0, -46.0),
+            Err(super::Error::InvalidArgument)
+        );
+        assert!(bus.written.is_empty());
+    }
+
+    #[test]
+    fn test_measure_voc_index() {
+        let mut bus = MockBus::new(&[0x75, 0x30, 0x08]);
+        let mut sensor = SGP40::new(&mut bus, NoopDelay);
+        let mut algorithm = VocAlgorithm::new();
+
+        // The algorithm reports 0 during its initial blackout.
+        for _ in 0..46 {
+            assert_eq!(sensor.measure_voc_index(&mut algorithm, 50.0, 25.0), Ok(0));
+        }
+        let index = sensor.measure_voc_index(&mut algorithm, 50.0, 25.0);
+        assert!(matches!(index, Ok(index) if index > 0), "{index:?}");
+        // Every sample is measured with the humidity and temperature compensation.
+        assert_eq!(bus.written.len(), 47 * 8);
+        assert!(
+            bus.written
+                .chunks(8)
+                .all(|frame| frame == [0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93])
+        );
+    }
+
+    #[test]
+    fn test_turn_heater_off() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SGP40::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.turn_heater_off(), Ok(()));
+        assert_eq!(bus.written, [0x36, 0x15]);
+    }
+
+    #[test]
+    fn test_measure_voc_index_duty_cycled() {
+        let mut bus = MockBus::new(&[0x75, 0x30, 0x08]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SGP40::new(&mut bus, &mut delay);
+        let mut algorithm = VocAlgorithm::with_sampling_interval(10.0);
+
+        assert_eq!(
+            sensor.measure_voc_index_duty_cycled(&mut algorithm, 50.0, 25.0),
+            Ok(0)
+        );
+        assert_eq!(
+            bus.written,
+            [
+                0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93, // measure, discarded
+                0x26, 0x0f, 0x80, 0x00, 0xa2, 0x66, 0x66, 0x93, // measure
+                0x36, 0x15, // heater off
+            ]
+        );
+        // The whole call takes exactly one sampling interval.
+        assert_eq!(delay.total_ns, 10_000_000_000);
+    }
+
+    #[test]
+    fn test_self_test() {
+        let mut bus = MockBus::new(&[0xd4, 0x00, 0xc6]);
+        let mut delay = RecordingDelay::default();
+        let mut sensor = SGP40::new(&mut bus, &mut delay);
+
+        assert_eq!(sensor.self_test(), Ok(true));
+        assert_eq!(bus.written, [0x28, 0x0e]);
+        assert_eq!(delay.total_ns, 320_000_000);
+    }
+
+    #[test]
+    fn test_self_test_failed() {
+        let bus = DummyBus {
+            response: &[0x4b, 0x00, 0x12],
+        };
+        let mut sensor = SGP40::new(bus, NoopDelay);
+
+        assert_eq!(sensor.self_test(), Ok(false));
+    }
+
+    #[test]
+    fn test_self_test_unexpected_result() {
+        let bus = DummyBus {
+            response: &[0x12, 0x34, 0x37],
+        };
+        let mut sensor = SGP40::new(bus, NoopDelay);
+
+        assert_eq!(
+            sensor.self_test(),
+            Err(super::Error::UnexpectedSelfTestResult(0x1234))
+        );
+    }
+
+    #[test]
+    fn test_poll_self_test() {
+        let mut bus = MockBus::new(&[]);
+        let mut sensor = SGP40::new(&mut bus, NoopDelay);
+
+        assert_eq!(sensor.start_self_test(), Ok(()));
+        assert_eq!(sensor.poll_self_test(), Ok(None));
+        assert_eq!(bus.written, [0x28, 0x0e]);
+
+        let bus = DummyBus {
+            response: &[0xd4, 0x00, 0xc6],
+        };
+        let mut sensor = SGP40::new(bus, NoopDelay);
+
+        assert_eq!(sensor.poll_self_test(), Ok(Some(true)));
     }
 }
@@ -0,0 +1,180 @@
+//! Bus and delay fixtures shared by the driver tests.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DummyError {
+    InvalidTest,
+    Nack,
+}
+
+impl embedded_hal::i2c::Error for DummyError {
+    fn kind(&self) -> ErrorKind {
+        match &self {
+            DummyError::InvalidTest => ErrorKind::Other,
+            DummyError::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+        }
+    }
+}
+
+/// Copies `response` to the read buffer. An empty response emulates a sensor
+/// that is still busy and does not acknowledge the read.
+fn respond(response: &[u8], buffer: &mut [u8]) -> Result<(), DummyError> {
+    if response.is_empty() {
+        return Err(DummyError::Nack);
+    }
+    if buffer.len() != response.len() {
+        return Err(DummyError::InvalidTest);
+    }
+
+    buffer.copy_from_slice(response);
+
+    Ok(())
+}
+
+/// Accepts every write and replies to every read with the same response.
+pub struct DummyBus<'a> {
+    pub response: &'a [u8],
+}
+
+impl DummyBus<'_> {
+    fn handle(&mut self, operations: &mut [Operation<'_>]) -> Result<(), DummyError> {
+        match operations {
+            [Operation::Write(_)] => Ok(()),
+            [Operation::Read(response)] => respond(self.response, response),
+            // Other transactions are invalid
+            _ => Err(DummyError::InvalidTest),
+        }
+    }
+}
+
+impl ErrorType for DummyBus<'_> {
+    type Error = DummyError;
+}
+
+impl I2c for DummyBus<'_> {
+    fn transaction(
+        &mut self,
+        _address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.handle(operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c for DummyBus<'_> {
+    async fn transaction(
+        &mut self,
+        _address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.handle(operations)
+    }
+}
+
+/// Records writes and replies to reads with the queued responses in order,
+/// repeating the last one.
+#[derive(Debug)]
+pub struct MockBus<'a> {
+    pub responses: Vec<&'a [u8]>,
+    pub written: Vec<u8>,
+    pub nack_next_write: bool,
+}
+
+impl<'a> MockBus<'a> {
+    pub fn new(response: &'a [u8]) -> Self {
+        Self::sequence(&[response])
+    }
+
+    pub fn sequence(responses: &[&'a [u8]]) -> Self {
+        Self {
+            responses: responses.to_vec(),
+            written: Vec::new(),
+            nack_next_write: false,
+        }
+    }
+
+    fn handle(&mut self, operations: &mut [Operation<'_>]) -> Result<(), DummyError> {
+        match operations {
+            [Operation::Write(_)] if self.nack_next_write => {
+                self.nack_next_write = false;
+
+                Err(DummyError::Nack)
+            }
+            [Operation::Write(data)] => {
+                self.written.extend_from_slice(data);
+
+                Ok(())
+            }
+            [Operation::Read(response)] => {
+                let next = if self.responses.len() > 1 {
+                    self.responses.remove(0)
+                } else {
+                    self.responses[0]
+                };
+
+                respond(next, response)
+            }
+            // Other transactions are invalid
+            _ => Err(DummyError::InvalidTest),
+        }
+    }
+}
+
+impl ErrorType for MockBus<'_> {
+    type Error = DummyError;
+}
+
+impl I2c for MockBus<'_> {
+    fn transaction(
+        &mut self,
+        _address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.handle(operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c for MockBus<'_> {
+    async fn transaction(
+        &mut self,
+        _address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.handle(operations)
+    }
+}
+
+#[derive(Debug)]
+pub struct NoopDelay;
+
+impl DelayNs for NoopDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::delay::DelayNs for NoopDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Sums up all requested delays.
+#[derive(Debug, Default)]
+pub struct RecordingDelay {
+    pub total_ns: u64,
+}
+
+impl DelayNs for RecordingDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.total_ns += ns as u64;
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::delay::DelayNs for RecordingDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.total_ns += ns as u64;
+    }
+}
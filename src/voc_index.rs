@@ -0,0 +1,602 @@
+//! Sensirion Gas Index Algorithm for the SGP40 VOC signal.
+//!
+//! Port of version 3.2.0 of Sensirion's reference implementation
+//! (<https://github.com/Sensirion/gas-index-algorithm>), limited to the VOC
+//! index. The algorithm turns raw signal ticks into a VOC index from 1 to 500,
+//! where 100 is the average over the learning time. It must be fed one sample
+//! per sampling interval.
+
+use libm::{expf, sqrtf};
+use thiserror::Error;
+
+const DEFAULT_SAMPLING_INTERVAL: f32 = 1.0;
+const INITIAL_BLACKOUT: f32 = 45.0;
+const INDEX_GAIN: f32 = 230.0;
+const SRAW_STD_INITIAL: f32 = 50.0;
+const SRAW_STD_BONUS: f32 = 220.0;
+const TAU_MEAN_HOURS: f32 = 12.0;
+const TAU_VARIANCE_HOURS: f32 = 12.0;
+const TAU_INITIAL_MEAN: f32 = 20.0;
+const INIT_DURATION_MEAN: f32 = 3600.0 * 0.75;
+const INIT_TRANSITION_MEAN: f32 = 0.01;
+const TAU_INITIAL_VARIANCE: f32 = 2500.0;
+const INIT_DURATION_VARIANCE: f32 = 3600.0 * 1.45;
+const INIT_TRANSITION_VARIANCE: f32 = 0.01;
+const GATING_THRESHOLD: f32 = 340.0;
+const GATING_THRESHOLD_INITIAL: f32 = 510.0;
+const GATING_THRESHOLD_TRANSITION: f32 = 0.09;
+const GATING_MAX_DURATION_MINUTES: f32 = 60.0 * 3.0;
+const GATING_MAX_RATIO: f32 = 0.3;
+const SIGMOID_L: f32 = 500.0;
+const SIGMOID_K: f32 = -0.0065;
+const SIGMOID_X0: f32 = 213.0;
+const INDEX_OFFSET_DEFAULT: f32 = 100.0;
+const LP_TAU_FAST: f32 = 20.0;
+const LP_TAU_SLOW: f32 = 500.0;
+const LP_ALPHA: f32 = -0.2;
+const SRAW_MINIMUM: u16 = 20000;
+const MVE_GAMMA_SCALING: f32 = 64.0;
+const MVE_ADDITIONAL_GAMMA_MEAN_SCALING: f32 = 8.0;
+const MVE_FIX16_MAX: f32 = 32767.0;
+
+const STATE_VERSION: u8 = 1;
+/// Length of the blob produced by [`VocAlgorithm::export_state`].
+pub const STATE_LEN: usize = 13;
+/// Sensirion recommends restoring the state only if the sensor was off for
+/// less than about 10 minutes.
+pub const MAX_STATE_AGE_SECONDS: u32 = 600;
+
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, Error)]
+#[error("tuning parameter out of range")]
+pub struct InvalidTuningParameters;
+
+#[derive(Clone, Copy, Hash, Debug, PartialEq, Eq, Error)]
+#[error("invalid algorithm state")]
+pub struct InvalidState;
+
+/// Tunable parameters of the algorithm. The defaults are the ones
+/// recommended by Sensirion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TuningParameters {
+    /// VOC index representing typical (average) conditions, 1 to 250.
+    pub index_offset: u16,
+    /// Time constant of the long-term mean estimate in hours, 1 to 1000.
+    pub learning_time_offset_hours: u16,
+    /// Time constant of the long-term variance estimate in hours, 1 to 1000.
+    pub learning_time_gain_hours: u16,
+    /// Maximum duration of gating (freezing of the estimator during high VOC
+    /// index signal) in minutes, 0 to 3000. Zero disables the gating.
+    pub gating_max_duration_minutes: u16,
+    /// Initial estimate for the standard deviation, 10 to 5000.
+    pub std_initial: u16,
+    /// Gain factor to amplify or attenuate the VOC index output, 1 to 1000.
+    pub gain_factor: u16,
+}
+
+impl Default for TuningParameters {
+    fn default() -> Self {
+        Self {
+            index_offset: INDEX_OFFSET_DEFAULT as u16,
+            learning_time_offset_hours: TAU_MEAN_HOURS as u16,
+            learning_time_gain_hours: TAU_VARIANCE_HOURS as u16,
+            gating_max_duration_minutes: GATING_MAX_DURATION_MINUTES as u16,
+            std_initial: SRAW_STD_INITIAL as u16,
+            gain_factor: INDEX_GAIN as u16,
+        }
+    }
+}
+
+impl TuningParameters {
+    fn check(&self) -> Result<(), InvalidTuningParameters> {
+        let valid = (1..=250).contains(&self.index_offset)
+            && (1..=1000).contains(&self.learning_time_offset_hours)
+            && (1..=1000).contains(&self.learning_time_gain_hours)
+            && self.gating_max_duration_minutes <= 3000
+            && (10..=5000).contains(&self.std_initial)
+            && (1..=1000).contains(&self.gain_factor);
+
+        if valid {
+            Ok(())
+        } else {
+            Err(InvalidTuningParameters)
+        }
+    }
+}
+
+fn sigmoid(sample: f32, x0: f32, k: f32) -> f32 {
+    let x = k * (sample - x0);
+    if x < -50.0 {
+        1.0
+    } else if x > 50.0 {
+        0.0
+    } else {
+        1.0 / (1.0 + expf(x))
+    }
+}
+
+/// Estimates the long-term mean and standard deviation of the raw signal.
+#[derive(Debug, Clone, Copy)]
+struct MeanVarianceEstimator {
+    initialized: bool,
+    mean: f32,
+    sraw_offset: f32,
+    std: f32,
+    gamma_mean: f32,
+    gamma_variance: f32,
+    gamma_initial_mean: f32,
+    gamma_initial_variance: f32,
+    current_gamma_mean: f32,
+    current_gamma_variance: f32,
+    uptime_gamma: f32,
+    uptime_gating: f32,
+    gating_duration_minutes: f32,
+}
+
+impl MeanVarianceEstimator {
+    fn new(sampling_interval: f32, tuning: &TuningParameters) -> Self {
+        let interval_hours = sampling_interval / 3600.0;
+
+        Self {
+            initialized: false,
+            mean: 0.0,
+            sraw_offset: 0.0,
+            std: tuning.std_initial as f32,
+            gamma_mean: (MVE_ADDITIONAL_GAMMA_MEAN_SCALING * MVE_GAMMA_SCALING * interval_hours)
+                / (tuning.learning_time_offset_hours as f32 + interval_hours),
+            gamma_variance: (MVE_GAMMA_SCALING * interval_hours)
+                / (tuning.learning_time_gain_hours as f32 + interval_hours),
+            gamma_initial_mean: (MVE_ADDITIONAL_GAMMA_MEAN_SCALING
+                * MVE_GAMMA_SCALING
+                * sampling_interval)
+                / (TAU_INITIAL_MEAN + sampling_interval),
+            gamma_initial_variance: (MVE_GAMMA_SCALING * sampling_interval)
+                / (TAU_INITIAL_VARIANCE + sampling_interval),
+            current_gamma_mean: 0.0,
+            current_gamma_variance: 0.0,
+            uptime_gamma: 0.0,
+            uptime_gating: 0.0,
+            gating_duration_minutes: 0.0,
+        }
+    }
+
+    fn mean(&self) -> f32 {
+        self.mean + self.sraw_offset
+    }
+
+    fn set_states(&mut self, mean: f32, std: f32, uptime_gamma: f32) {
+        self.mean = mean;
+        self.std = std;
+        self.uptime_gamma = uptime_gamma;
+        self.initialized = true;
+    }
+
+    fn calculate_gamma(&mut self, gas_index: f32, sampling_interval: f32, gating_max_minutes: f32) {
+        let uptime_limit = MVE_FIX16_MAX - sampling_interval;
+        if self.uptime_gamma < uptime_limit {
+            self.uptime_gamma += sampling_interval;
+        }
+        if self.uptime_gating < uptime_limit {
+            self.uptime_gating += sampling_interval;
+        }
+
+        let sigmoid_gamma_mean =
+            sigmoid(self.uptime_gamma, INIT_DURATION_MEAN, INIT_TRANSITION_MEAN);
+        let gamma_mean =
+            self.gamma_mean + (self.gamma_initial_mean - self.gamma_mean) * sigmoid_gamma_mean;
+        let gating_threshold_mean = GATING_THRESHOLD
+            + (GATING_THRESHOLD_INITIAL - GATING_THRESHOLD)
+                * sigmoid(self.uptime_gating, INIT_DURATION_MEAN, INIT_TRANSITION_MEAN);
+        let sigmoid_gating_mean = sigmoid(
+            gas_index,
+            gating_threshold_mean,
+            GATING_THRESHOLD_TRANSITION,
+        );
+        self.current_gamma_mean = sigmoid_gating_mean * gamma_mean;
+
+        let sigmoid_gamma_variance = sigmoid(
+            self.uptime_gamma,
+            INIT_DURATION_VARIANCE,
+            INIT_TRANSITION_VARIANCE,
+        );
+        let gamma_variance = self.gamma_variance
+            + (self.gamma_initial_variance - self.gamma_variance)
+                * (sigmoid_gamma_variance - sigmoid_gamma_mean);
+        let gating_threshold_variance = GATING_THRESHOLD
+            + (GATING_THRESHOLD_INITIAL - GATING_THRESHOLD)
+                * sigmoid(
+                    self.uptime_gating,
+                    INIT_DURATION_VARIANCE,
+                    INIT_TRANSITION_VARIANCE,
+                );
+        let sigmoid_gating_variance = sigmoid(
+            gas_index,
+            gating_threshold_variance,
+            GATING_THRESHOLD_TRANSITION,
+        );
+        self.current_gamma_variance = sigmoid_gating_variance * gamma_variance;
+
+        self.gating_duration_minutes += (sampling_interval / 60.0)
+            * ((1.0 - sigmoid_gating_mean) * (1.0 + GATING_MAX_RATIO) - GATING_MAX_RATIO);
+        if self.gating_duration_minutes < 0.0 {
+            self.gating_duration_minutes = 0.0;
+        }
+        if self.gating_duration_minutes > gating_max_minutes {
+            self.uptime_gating = 0.0;
+        }
+    }
+
+    fn process(
+        &mut self,
+        sraw: f32,
+        gas_index: f32,
+        sampling_interval: f32,
+        gating_max_minutes: f32,
+    ) {
+        if !self.initialized {
+            self.initialized = true;
+            self.sraw_offset = sraw;
+            self.mean = 0.0;
+            return;
+        }
+
+        if self.mean >= 100.0 || self.mean <= -100.0 {
+            self.sraw_offset += self.mean;
+            self.mean = 0.0;
+        }
+
+        let sraw = sraw - self.sraw_offset;
+        self.calculate_gamma(gas_index, sampling_interval, gating_max_minutes);
+        let delta_sgp = (sraw - self.mean) / MVE_GAMMA_SCALING;
+        let c = self.std + delta_sgp.abs();
+        let additional_scaling = if c > 1440.0 {
+            (c / 1440.0) * (c / 1440.0)
+        } else {
+            1.0
+        };
+        self.std = sqrtf(additional_scaling * (MVE_GAMMA_SCALING - self.current_gamma_variance))
+            * sqrtf(
+                self.std * (self.std / (MVE_GAMMA_SCALING * additional_scaling))
+                    + ((self.current_gamma_variance * delta_sgp) / additional_scaling) * delta_sgp,
+            );
+        self.mean += (self.current_gamma_mean * delta_sgp) / MVE_ADDITIONAL_GAMMA_MEAN_SCALING;
+    }
+}
+
+/// Low-pass filter with a time constant that adapts to the rate of change.
+#[derive(Debug, Clone, Copy)]
+struct AdaptiveLowpass {
+    a1: f32,
+    a2: f32,
+    initialized: bool,
+    x1: f32,
+    x2: f32,
+    x3: f32,
+}
+
+impl AdaptiveLowpass {
+    fn new(sampling_interval: f32) -> Self {
+        Self {
+            a1: sampling_interval / (LP_TAU_FAST + sampling_interval),
+            a2: sampling_interval / (LP_TAU_SLOW + sampling_interval),
+            initialized: false,
+            x1: 0.0,
+            x2: 0.0,
+            x3: 0.0,
+        }
+    }
+
+    fn process(&mut self, sample: f32, sampling_interval: f32) -> f32 {
+        if !self.initialized {
+            self.x1 = sample;
+            self.x2 = sample;
+            self.x3 = sample;
+            self.initialized = true;
+        }
+
+        self.x1 = (1.0 - self.a1) * self.x1 + self.a1 * sample;
+        self.x2 = (1.0 - self.a2) * self.x2 + self.a2 * sample;
+
+        let abs_delta = (self.x1 - self.x2).abs();
+        let f1 = expf(LP_ALPHA * abs_delta);
+        let tau_a = (LP_TAU_SLOW - LP_TAU_FAST) * f1 + LP_TAU_FAST;
+        let a3 = sampling_interval / (sampling_interval + tau_a);
+        self.x3 = (1.0 - a3) * self.x3 + a3 * sample;
+        self.x3
+    }
+}
+
+/// VOC index algorithm state.
+#[derive(Debug, Clone, Copy)]
+pub struct VocAlgorithm {
+    sampling_interval: f32,
+    tuning: TuningParameters,
+    uptime: f32,
+    sraw: f32,
+    gas_index: f32,
+    estimator: MeanVarianceEstimator,
+    lowpass: AdaptiveLowpass,
+}
+
+impl Default for VocAlgorithm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VocAlgorithm {
+    /// Creates the algorithm for the default sampling interval of 1 s.
+    pub fn new() -> Self {
+        Self::with_sampling_interval(DEFAULT_SAMPLING_INTERVAL)
+    }
+
+    /// Creates the algorithm for a sampling interval in seconds. Sensirion
+    /// tested intervals of 1 s and 10 s, the latter for low power operation.
+    pub fn with_sampling_interval(seconds: f32) -> Self {
+        let tuning = TuningParameters::default();
+
+        Self {
+            sampling_interval: seconds,
+            tuning,
+            uptime: 0.0,
+            sraw: 0.0,
+            gas_index: 0.0,
+            estimator: MeanVarianceEstimator::new(seconds, &tuning),
+            lowpass: AdaptiveLowpass::new(seconds),
+        }
+    }
+
+    /// Returns the sampling interval in seconds.
+    pub fn sampling_interval(&self) -> f32 {
+        self.sampling_interval
+    }
+
+    /// Returns the current tuning parameters.
+    pub fn tuning_parameters(&self) -> TuningParameters {
+        self.tuning
+    }
+
+    /// Sets the tuning parameters. Resets the learned state.
+    pub fn set_tuning_parameters(
+        &mut self,
+        tuning: TuningParameters,
+    ) -> Result<(), InvalidTuningParameters> {
+        tuning.check()?;
+
+        self.tuning = tuning;
+        self.reset_instances();
+        Ok(())
+    }
+
+    /// Resets the learned state, keeping the tuning parameters.
+    pub fn reset(&mut self) {
+        self.uptime = 0.0;
+        self.sraw = 0.0;
+        self.gas_index = 0.0;
+        self.reset_instances();
+    }
+
+    fn reset_instances(&mut self) {
+        self.estimator = MeanVarianceEstimator::new(self.sampling_interval, &self.tuning);
+        self.lowpass = AdaptiveLowpass::new(self.sampling_interval);
+    }
+
+    /// Exports the learned mean, standard deviation and uptime as a versioned
+    /// blob. Returns `None` until the algorithm has processed a sample after
+    /// the initial blackout.
+    pub fn export_state(&self) -> Option<[u8; STATE_LEN]> {
+        if !self.estimator.initialized {
+            return None;
+        }
+
+        let mut state = [0; STATE_LEN];
+        state[0] = STATE_VERSION;
+        state[1..5].copy_from_slice(&self.estimator.mean().to_le_bytes());
+        state[5..9].copy_from_slice(&self.estimator.std.to_le_bytes());
+        state[9..13].copy_from_slice(&self.estimator.uptime_gamma.to_le_bytes());
+        Some(state)
+    }
+
+    /// Resets the algorithm and restores a state produced by export_state.
+    /// `elapsed_seconds` is the time since the state was exported. If it exceeds
+    /// [`MAX_STATE_AGE_SECONDS`], the state is stale and the algorithm starts
+    /// fresh instead. Returns true if the state was restored.
+    pub fn restore_state(
+        &mut self,
+        state: &[u8],
+        elapsed_seconds: u32,
+    ) -> Result<bool, InvalidState> {
+        let state: &[u8; STATE_LEN] = state.try_into().map_err(|_| InvalidState)?;
+        if state[0] != STATE_VERSION {
+            return Err(InvalidState);
+        }
+
+        let value = |offset: usize| {
+            f32::from_le_bytes([
+                state[offset],
+                state[offset + 1],
+                state[offset + 2],
+                state[offset + 3],
+            ])
+        };
+        let (mean, std, uptime_gamma) = (value(1), value(5), value(9));
+        if !(mean.is_finite() && std.is_finite() && std > 0.0 && uptime_gamma >= 0.0) {
+            return Err(InvalidState);
+        }
+
+        self.reset();
+        if elapsed_seconds > MAX_STATE_AGE_SECONDS {
+            return Ok(false);
+        }
+
+        self.estimator.set_states(mean, std, uptime_gamma);
+        self.sraw = mean;
+        Ok(true)
+    }
+
+    /// Processes a raw signal sample and returns the VOC index.
+    /// Returns 0 during the first 45 seconds, while the sensor stabilizes.
+    pub fn process(&mut self, sraw: u16) -> u16 {
+        if self.uptime <= INITIAL_BLACKOUT {
+            self.uptime += self.sampling_interval;
+        } else {
+            if sraw > 0 && sraw < 65000 {
+                let sraw = sraw.clamp(SRAW_MINIMUM + 1, SRAW_MINIMUM + 32767);
+                self.sraw = (sraw - SRAW_MINIMUM) as f32;
+            }
+
+            self.gas_index = self.scale_sigmoid(self.mox_model(self.sraw));
+            self.gas_index = self.lowpass.process(self.gas_index, self.sampling_interval);
+            if self.gas_index < 0.5 {
+                self.gas_index = 0.5;
+            }
+
+            if self.sraw > 0.0 {
+                self.estimator.process(
+                    self.sraw,
+                    self.gas_index,
+                    self.sampling_interval,
+                    self.tuning.gating_max_duration_minutes as f32,
+                );
+            }
+        }
+
+        (self.gas_index + 0.5) as u16
+    }
+
+    fn mox_model(&self, sraw: f32) -> f32 {
+        ((sraw - self.estimator.mean()) / -(self.estimator.std + SRAW_STD_BONUS))
+            * self.tuning.gain_factor as f32
+    }
+
+    fn scale_sigmoid(&self, sample: f32) -> f32 {
+        let index_offset = self.tuning.index_offset as f32;
+        let x = SIGMOID_K * (sample - SIGMOID_X0);
+
+        if x < -50.0 {
+            SIGMOID_L
+        } else if x > 50.0 {
+            0.0
+        } else if sample >= 0.0 {
+            let shift = (SIGMOID_L - 5.0 * index_offset) / 4.0;
+            (SIGMOID_L + shift) / (1.0 + expf(x)) - shift
+        } else {
+            (index_offset / INDEX_OFFSET_DEFAULT) * (SIGMOID_L / (1.0 + expf(x)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InvalidState, InvalidTuningParameters, TuningParameters, VocAlgorithm};
+
+    #[test]
+    fn test_initial_blackout() {
+        let mut algorithm = VocAlgorithm::new();
+
+        for _ in 0..46 {
+            assert_eq!(algorithm.process(30000), 0);
+        }
+        assert_ne!(algorithm.process(30000), 0);
+    }
+
+    #[test]
+    fn test_constant_signal_settles_at_offset() {
+        let mut algorithm = VocAlgorithm::new();
+
+        let mut index = 0;
+        for _ in 0..3600 {
+            index = algorithm.process(30000);
+        }
+        assert_eq!(index, 100);
+    }
+
+    #[test]
+    fn test_lower_signal_raises_index() {
+        let mut algorithm = VocAlgorithm::new();
+
+        for _ in 0..3600 {
+            algorithm.process(30000);
+        }
+
+        let mut index = 0;
+        for _ in 0..60 {
+            index = algorithm.process(29000);
+        }
+        assert!(index > 150, "index {index}");
+    }
+
+    #[test]
+    fn test_tuning_parameters() {
+        let mut algorithm = VocAlgorithm::with_sampling_interval(10.0);
+        assert_eq!(algorithm.sampling_interval(), 10.0);
+
+        let tuning = TuningParameters {
+            index_offset: 0,
+            ..TuningParameters::default()
+        };
+        assert_eq!(
+            algorithm.set_tuning_parameters(tuning),
+            Err(InvalidTuningParameters)
+        );
+
+        let tuning = TuningParameters {
+            index_offset: 150,
+            ..TuningParameters::default()
+        };
+        assert_eq!(algorithm.set_tuning_parameters(tuning), Ok(()));
+        assert_eq!(algorithm.tuning_parameters(), tuning);
+    }
+
+    #[test]
+    fn test_export_before_initialization() {
+        let mut algorithm = VocAlgorithm::new();
+
+        algorithm.process(30000);
+        assert_eq!(algorithm.export_state(), None);
+    }
+
+    #[test]
+    fn test_restore_state() {
+        let mut algorithm = VocAlgorithm::new();
+        for _ in 0..3600 {
+            algorithm.process(29000);
+        }
+        let state = algorithm.export_state().unwrap();
+
+        let mut restored = VocAlgorithm::new();
+        assert_eq!(restored.restore_state(&state, 60), Ok(true));
+        assert_eq!(restored.export_state(), Some(state));
+
+        // The learned baseline applies as soon as the blackout is over.
+        let mut index = 0;
+        for _ in 0..47 {
+            index = restored.process(29000);
+        }
+        assert!((95..=105).contains(&index), "index {index}");
+    }
+
+    #[test]
+    fn test_restore_stale_state() {
+        let mut algorithm = VocAlgorithm::new();
+        for _ in 0..100 {
+            algorithm.process(30000);
+        }
+        let state = algorithm.export_state().unwrap();
+
+        assert_eq!(algorithm.restore_state(&state, 601), Ok(false));
+        assert_eq!(algorithm.export_state(), None);
+    }
+
+    #[test]
+    fn test_restore_invalid_state() {
+        let mut algorithm = VocAlgorithm::new();
+        for _ in 0..100 {
+            algorithm.process(30000);
+        }
+        let mut state = algorithm.export_state().unwrap();
+
+        assert_eq!(algorithm.restore_state(&state[..12], 0), Err(InvalidState));
+        state[0] = 2;
+        assert_eq!(algorithm.restore_state(&state, 0), Err(InvalidState));
+    }
+}